@@ -0,0 +1,223 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use tetrs::board::{Board, RotationDirection, Stack};
+use tetrs::bot::{self, Weights};
+use tetrs::mino::{Tetromino, TetrominoType};
+use tetrs::movegen;
+use tetrs::position::Position;
+
+const DIMS: (usize, usize) = (10, 20);
+const SEEDS: [u64; 4] = [1, 2, 3, 4];
+
+// A board with `pieces` placed anywhere they fit, chosen by `seed`, so the
+// stack is as ragged as a bad game leaves it
+fn messy(seed: u64, pieces: usize) -> Board {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut board = Board::with_seed(DIMS, seed);
+    board.new_tetromino();
+
+    for _ in 0..pieces {
+        let placements = movegen::active_placements(&board);
+        // Keep low so the stack never reaches the top
+        let lowest = placements.iter().map(|placement| placement.y).max();
+        let low: Vec<_> = placements
+            .iter()
+            .filter(|placement| Some(placement.y + 3) >= lowest)
+            .collect();
+        if low.is_empty() {
+            break;
+        }
+        board.place(low[rng.gen_range(0..low.len())]);
+    }
+    board
+}
+
+// A board with the bottom four rows full but for the right column and an
+// upright I over the well
+fn tetris_ready() -> Board {
+    let position = Position::from_text(
+        "hold -\ncurrent I\nqueue -\nfield\n\
+         GGGGGGGGG.\nGGGGGGGGG.\nGGGGGGGGG.\nGGGGGGGGG.\n",
+    )
+    .unwrap();
+
+    let mut board = Board::with_seed(DIMS, 0);
+    board.load_position(&position);
+    board.new_tetromino();
+    board.rotate(RotationDirection::Clockwise);
+    for _ in 0..DIMS.0 {
+        board.move_tetromino((1, 0));
+    }
+    board
+}
+
+fn collision(c: &mut Criterion) {
+    let board = messy(SEEDS[0], 30);
+    let minos: Vec<Tetromino> = TetrominoType::ALL
+        .into_iter()
+        .flat_map(|kind| {
+            (0..4).map(move |orientation| {
+                let mut mino = Tetromino::new(kind);
+                mino.orientation = orientation;
+                mino
+            })
+        })
+        .collect();
+
+    // Every orientation of every piece at every spot on the board, edges
+    // and all
+    c.bench_function("collides/every_spot", |b| {
+        b.iter(|| {
+            let mut hits = 0;
+            for mino in &minos {
+                for y in -1..=board.height as i32 {
+                    for x in -2..=board.width as i32 {
+                        hits += usize::from(black_box(&board).collides(mino, x, y));
+                    }
+                }
+            }
+            hits
+        })
+    });
+}
+
+fn rotation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rotate");
+    for seed in SEEDS {
+        // A piece dropped onto the stack, where turning it needs kicks
+        let mut board = messy(seed, 30);
+        board.soft_harddrop();
+
+        group.bench_function(format!("kicks/seed_{}", seed), |b| {
+            b.iter_batched(
+                || board.clone(),
+                |mut board| {
+                    for _ in 0..4 {
+                        board.rotate(RotationDirection::Clockwise);
+                    }
+                    for _ in 0..4 {
+                        board.rotate(RotationDirection::CounterClockwise);
+                    }
+                    board
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn dropping(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hard_drop");
+    group.bench_function("empty", |b| {
+        let mut board = Board::with_seed(DIMS, SEEDS[0]);
+        board.new_tetromino();
+        b.iter_batched(
+            || board.clone(),
+            |mut board| {
+                board.hard_drop();
+                board
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("messy", |b| {
+        let board = messy(SEEDS[0], 30);
+        b.iter_batched(
+            || board.clone(),
+            |mut board| {
+                board.hard_drop();
+                board
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+fn line_clears(c: &mut Criterion) {
+    // Lines only clear when a piece locks, so this is a hard drop that takes
+    // out four of them
+    let board = tetris_ready();
+    c.bench_function("clear_lines/tetris", |b| {
+        b.iter_batched(
+            || board.clone(),
+            |mut board| {
+                board.hard_drop();
+                assert_eq!(board.lines_cleared, 4);
+                board
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn simulation(c: &mut Criterion) {
+    const PIECES: usize = 1_000;
+
+    // Whole games of pieces dropped anywhere they fit, starting again after
+    // a top out
+    let mut group = c.benchmark_group("game");
+    group.throughput(Throughput::Elements(PIECES as u64));
+    group.bench_function("random_placements", |b| {
+        b.iter(|| {
+            let mut placed = 0;
+            for seed in SEEDS.iter().cycle() {
+                let mut rng = StdRng::seed_from_u64(*seed);
+                let mut board = Board::with_seed(DIMS, *seed);
+                board.new_tetromino();
+
+                while placed < PIECES && !board.topped_out {
+                    let placements = movegen::active_placements(&board);
+                    if placements.is_empty() {
+                        break;
+                    }
+                    board.place(&placements[rng.gen_range(0..placements.len())]);
+                    placed += 1;
+                }
+                if placed == PIECES {
+                    return board;
+                }
+            }
+            unreachable!()
+        })
+    });
+    group.finish();
+
+    // The bot thinks through the queue for every piece, so it gets fewer
+    // of them and fewer samples. benches/headless.rs plays a long game.
+    const BOT_PIECES: usize = 100;
+    let mut group = c.benchmark_group("bot");
+    group.sample_size(10);
+    group.bench_function("plan", |b| {
+        let board = messy(SEEDS[0], 30);
+        b.iter(|| bot::plan(black_box(&board), &Weights::default()))
+    });
+    group.throughput(Throughput::Elements(BOT_PIECES as u64));
+    group.bench_function(format!("{}_pieces", BOT_PIECES), |b| {
+        b.iter(|| {
+            let mut board = Board::with_seed(DIMS, SEEDS[0]);
+            board.new_tetromino();
+            for _ in 0..BOT_PIECES {
+                let Some(plan) = bot::plan(&board, &Weights::default()) else {
+                    break;
+                };
+                bot::play(&mut board, &plan);
+            }
+            board
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    collision,
+    rotation,
+    dropping,
+    line_clears,
+    simulation
+);
+criterion_main!(benches);
@@ -0,0 +1,58 @@
+// The bot playing a long game with no terminal or clock, timed once rather
+// than sampled like benches/engine.rs since a run takes a while. Games are
+// dealt from fixed seeds in turn, the next starting when one tops out, so
+// every run places the same pieces. Give fewer pieces for a quicker run.
+//
+//     cargo bench --bench headless [-- <pieces>]
+
+use std::env;
+use std::time::Instant;
+
+use tetrs::board::Board;
+use tetrs::bot::{self, Weights};
+
+const DIMS: (usize, usize) = (10, 20);
+const PIECES: usize = 10_000;
+
+fn main() {
+    // cargo bench passes --bench along to every target
+    let pieces = env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(PIECES);
+    let weights = Weights::default();
+
+    let mut placed = 0;
+    let mut lines = 0;
+    let mut games = 0;
+    let started = Instant::now();
+
+    for seed in 1.. {
+        if placed == pieces {
+            break;
+        }
+
+        let mut board = Board::with_seed(DIMS, seed);
+        board.new_tetromino();
+        games += 1;
+
+        while placed < pieces && !board.topped_out {
+            let Some(plan) = bot::plan(&board, &weights) else {
+                break;
+            };
+            bot::play(&mut board, &plan);
+            placed += 1;
+        }
+        lines += board.lines_cleared;
+    }
+
+    let elapsed = started.elapsed();
+    println!(
+        "headless bot: {} pieces, {} lines, {} game(s) in {:.2?} ({:.1} pieces/s)",
+        placed,
+        lines,
+        games,
+        elapsed,
+        placed as f64 / elapsed.as_secs_f64()
+    );
+}
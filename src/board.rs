@@ -1,40 +1,117 @@
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::Serialize;
+
+use crate::finesse::{self, FinesseStats};
 use crate::mino::{
     GhostType, PieceData, Tetromino, TetrominoType, LARGE_MINO_KICK_TABLE, SMALL_MINO_KICK_TABLE,
 };
-use crate::util::{gen_bag, mino_to_ghost};
+use crate::movegen::{self, Placement};
+use crate::position::Position;
+use crate::stats::{Stats, TSpin};
+use crate::util::{fnv1a, gen_bag, mino_to_ghost};
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Status {
     Empty,
     FillType(TetrominoType),
     FillGhost(GhostType),
+    Garbage,
+    // Where a trainer wants the next piece to go, drawn under the ghost and
+    // never part of the stack
+    Overlay(TetrominoType),
+    // The outline of a suggested placement, drawn like the overlay
+    Hint(TetrominoType),
+}
+
+// What ended a game that topped out
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TopOut {
+    // The next piece spawned overlapping the stack
+    BlockOut,
+    // Rising garbage pushed the stack or the active piece off the top
+    Garbage,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum RotationDirection {
     Clockwise,
     CounterClockwise,
 }
 
+// Everything a placement changes, so a position can be put back exactly as
+// it was, including the bag and counters.
+#[derive(Clone)]
+pub struct Snapshot {
+    tiles: Vec<Vec<Status>>,
+    rows: Vec<u16>,
+    garbage: Vec<u16>,
+    active_tetromino: Option<Tetromino>,
+    x: i32,
+    y: i32,
+    spawn: (i32, i32),
+    upcoming: Vec<TetrominoType>,
+    rng: StdRng,
+    held_piece: Option<TetrominoType>,
+    held: bool,
+    pieces_placed: usize,
+    lines_cleared: usize,
+    stats: Stats,
+    finesse: FinesseStats,
+}
+
+#[derive(Clone)]
 pub struct Board {
     pub width: usize,
     pub height: usize,
+    // What to draw in each cell: the colours of the stack, the active piece,
+    // its ghost and any overlay. Only the colours of locked cells are ever
+    // read back.
     pub tiles: Vec<Vec<Status>>,
     pub pieces_placed: usize,
     pub lines_cleared: usize,
-    pub upcoming_tiles: Vec<Vec<Status>>,
-    pub held_tiles: Vec<Vec<Status>>,
-    col_buffer: Vec<Vec<bool>>,
+    // Multiplies clear scores; the game mode raises it and sets the gravity
+    // to match
+    pub level: usize,
+    pub topped_out: bool,
+    // Why, once it has
+    pub top_out: Option<TopOut>,
+    // Whether a piece resting on the stack locks by itself after the lock
+    // delay, rather than only on a hard drop
+    pub auto_lock: bool,
+    // When off, a blocked spawn clears rows off the bottom instead of ending
+    // the game
+    pub can_top_out: bool,
+    // When on, only the pieces already queued are dealt and no new bags are
+    // added, for puzzles with a set sequence
+    pub fixed_sequence: bool,
+    pub finesse: FinesseStats,
+    pub stats: Stats,
+    // The locked stack, one bitmask per row with bit x set for column x.
+    // Collisions and line clears go by this alone.
+    rows: Vec<u16>,
+    // The garbage cells among them, masked the same way
+    garbage: Vec<u16>,
     active_tetromino: Option<Tetromino>,
     x: i32,
     y: i32,
     upcoming: Vec<TetrominoType>,
+    seed: u64,
+    rng: StdRng,
     held_piece: Option<TetrominoType>,
     held: bool,
-    gravity_timer: Instant,
+    spawn: (i32, i32),
+    piece_inputs: usize,
+    last_rotated: bool,
+    last_kick_far: bool,
+    // Game time as of the last update, which the timers count in
+    now: Duration,
+    gravity_timer: Duration,
     gravity_interval: Duration,
-    lock_delay_timer: Option<Instant>,
+    lock_delay_timer: Option<Duration>,
     lock_delay_interval: Duration,
     lock_delay_max: Duration,
     lock_delay_cur: Duration,
@@ -61,56 +138,217 @@ macro_rules! draw_piece {
     }};
 }
 
-macro_rules! check_col {
-    ($self:expr, $data:expr, $sizex:expr, $sizey:expr, $offset:expr) => {
-        for y in 0..$sizey {
-            for x in 0..$sizex {
-                if $data[y][x] {
-                    if $self.y as i32 + y as i32 + $offset.1 >= $self.height as i32
-                        || $self.x as i32 + x as i32 + $offset.0 < 0
-                        || $self.x as i32 + x as i32 + $offset.0 >= $self.width as i32
-                    {
-                        return true;
-                    }
+// `mask` moved right by `x` columns, or None when part of it would leave a
+// board `width` wide
+pub fn shift_mask(mask: u16, x: i32, width: usize) -> Option<u16> {
+    // Sixteen spare columns on each side catch whatever falls off an edge
+    let padded = (mask as u64) << 16;
+    let x = x.clamp(-16, 16);
+    let moved = if x < 0 { padded >> -x } else { padded << x };
 
-                    if $self.col_buffer[($self.y + y as i32 + $offset.1) as usize]
-                        [($self.x + x as i32 + $offset.0) as usize]
-                    {
-                        return true;
-                    }
+    (moved & 0xffff == 0 && moved >> (16 + width) == 0).then_some((moved >> 16) as u16)
+}
+
+// `mino` turned once in `dir`, with the SRS kicks to try in order
+pub fn rotation(mino: &Tetromino, dir: RotationDirection) -> (Tetromino, &'static [(i8, i8); 5]) {
+    let mut rotated = mino.clone();
+
+    let table_entry = match dir {
+        RotationDirection::Clockwise => match mino.orientation {
+            0 => 0,
+            1 => 2,
+            2 => 4,
+            _ => 6,
+        },
+        RotationDirection::CounterClockwise => match mino.orientation {
+            0 => 7,
+            1 => 1,
+            2 => 3,
+            _ => 5,
+        },
+    };
+
+    rotated.orientation = match dir {
+        RotationDirection::Clockwise => (mino.orientation + 1) % 4,
+        RotationDirection::CounterClockwise => (mino.orientation + 3) % 4,
+    };
+
+    let kick_table: &'static [[(i8, i8); 5]; 8] = match mino.piece_data {
+        PieceData::Small(_) => &SMALL_MINO_KICK_TABLE,
+        PieceData::Medium(_) => &SMALL_MINO_KICK_TABLE,
+        PieceData::Large(_) => &LARGE_MINO_KICK_TABLE,
+    };
+
+    (rotated, &kick_table[table_entry])
+}
+
+// The locked cells pieces move through and lock onto, one bitmask per row
+// with bit x set for column x. A board is one, and so is the bare stack a
+// search plays on, which copies far quicker.
+pub trait Stack {
+    fn width(&self) -> usize;
+    fn rows(&self) -> &[u16];
+
+    // Whether `mino` placed with its top left at (x, y) overlaps the stack or
+    // leaves the board. Only the locked stack counts, never the active piece.
+    fn collides(&self, mino: &Tetromino, x: i32, y: i32) -> bool {
+        mino.row_masks()
+            .iter()
+            .enumerate()
+            .filter(|(_, mask)| **mask != 0)
+            .any(|(row, mask)| {
+                let y = y + row as i32;
+                if y < 0 || y >= self.rows().len() as i32 {
+                    return true;
+                }
+                shift_mask(*mask, x, self.width())
+                    .is_none_or(|mask| self.rows()[y as usize] & mask != 0)
+            })
+    }
+
+    // Row `mino` would land on if hard dropped from (x, y)
+    fn drop_y(&self, mino: &Tetromino, x: i32, y: i32) -> i32 {
+        // The column never changes on the way down, so shift the masks once
+        let mut masks = [0; 4];
+        for (shifted, mask) in masks.iter_mut().zip(mino.row_masks()) {
+            if *mask != 0 {
+                match shift_mask(*mask, x, self.width()) {
+                    Some(mask) => *shifted = mask,
+                    None => return y,
                 }
             }
         }
-    };
+        let rows = self.rows();
+        let fits = |y: i32| {
+            masks.iter().enumerate().all(|(row, mask)| {
+                let y = y + row as i32;
+                *mask == 0 || (y >= 0 && (y as usize) < rows.len() && rows[y as usize] & mask == 0)
+            })
+        };
+
+        let mut y = y;
+        while fits(y + 1) {
+            y += 1;
+        }
+        y
+    }
+
+    // Where `mino` ends up after rotating from (x, y), trying each SRS kick in
+    // order. None when every kick collides.
+    fn kick(
+        &self,
+        mino: &Tetromino,
+        x: i32,
+        y: i32,
+        dir: RotationDirection,
+    ) -> Option<(Tetromino, i32, i32)> {
+        let (rotated, kicks) = rotation(mino, dir);
+
+        for &(kx, ky) in kicks {
+            let (nx, ny) = (x + kx as i32, y + ky as i32);
+            if !self.collides(&rotated, nx, ny) {
+                return Some((rotated, nx, ny));
+            }
+        }
+
+        None
+    }
+
+    // Three corner rule, with the two corners the T points at deciding
+    // between a mini and a full spin. Only counts for a T that locks straight
+    // after rotating at (x, y).
+    fn tspin(&self, mino: &Tetromino, x: i32, y: i32, kick_far: bool) -> TSpin {
+        if mino.tr_type != TetrominoType::T {
+            return TSpin::None;
+        }
+
+        let rows = self.rows();
+        let filled = |&(cx, cy): &(i32, i32)| {
+            let (x, y) = (x + cx, y + cy);
+            x < 0
+                || x >= self.width() as i32
+                || y >= rows.len() as i32
+                || (y >= 0 && rows[y as usize] & (1 << x) != 0)
+        };
+
+        let corners = [(0, 0), (2, 0), (0, 2), (2, 2)];
+        if corners.iter().filter(|corner| filled(corner)).count() < 3 {
+            return TSpin::None;
+        }
+
+        let front = match mino.orientation {
+            0 => [(0, 0), (2, 0)],
+            1 => [(2, 0), (2, 2)],
+            2 => [(0, 2), (2, 2)],
+            _ => [(0, 0), (0, 2)],
+        };
+
+        if front.iter().all(filled) || kick_far {
+            TSpin::Full
+        } else {
+            TSpin::Mini
+        }
+    }
+}
+
+impl Stack for Board {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn rows(&self) -> &[u16] {
+        &self.rows
+    }
 }
 
 impl Board {
     pub fn new(dims: (usize, usize)) -> Self {
-        let tiles = vec![vec![Status::Empty; dims.0]; dims.1 + 10];
-        let col_buffer = vec![vec![false; dims.0]; dims.1 + 10];
-        let upcoming_tiles = vec![vec![Status::Empty; 4]; 20];
-        let held_tiles = vec![vec![Status::Empty; 4]; 4];
+        Self::with_seed(dims, rand::random())
+    }
 
+    // The same seed always deals the same pieces, so a game can be replayed
+    // or compared against another run of the same sequence.
+    pub fn with_seed(dims: (usize, usize), seed: u64) -> Self {
+        // Rows are 16 bit masks
+        assert!(dims.0 <= 16, "boards are at most 16 wide");
+        let tiles = vec![vec![Status::Empty; dims.0]; dims.1 + 10];
+        let rows = vec![0; dims.1 + 10];
+        let garbage = vec![0; dims.1 + 10];
 
-        let mut upcoming = gen_bag();
-        upcoming.extend(gen_bag());
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut upcoming = gen_bag(&mut rng);
+        upcoming.extend(gen_bag(&mut rng));
 
         Board {
             width: dims.0,
             height: dims.1 + 10,
             tiles,
-            col_buffer,
+            rows,
+            garbage,
             pieces_placed: 0,
             lines_cleared: 0,
-            upcoming_tiles,
-            held_tiles,
+            level: 1,
+            topped_out: false,
+            top_out: None,
+            auto_lock: true,
+            can_top_out: true,
+            fixed_sequence: false,
+            finesse: FinesseStats::default(),
+            stats: Stats::default(),
             active_tetromino: None,
             x: (dims.0 / 2 - 2) as i32,
             y: 10,
             upcoming,
+            seed,
+            rng,
             held_piece: None,
             held: false,
-            gravity_timer: Instant::now(),
+            spawn: ((dims.0 / 2 - 2) as i32, 10),
+            piece_inputs: 0,
+            last_rotated: false,
+            last_kick_far: false,
+            now: Duration::ZERO,
+            gravity_timer: Duration::ZERO,
             gravity_interval: Duration::from_millis(1000),
             lock_delay_timer: None,
             lock_delay_interval: Duration::from_millis(500),
@@ -128,240 +366,319 @@ impl Board {
         false
     }
 
+    // The first cause counts when garbage and a block out land together
+    fn end(&mut self, cause: TopOut) {
+        self.topped_out = true;
+        self.top_out.get_or_insert(cause);
+    }
 
-    fn clear_upcoming(&mut self) {
-        for y in 0..20 {
-            for x in 0..4 {
-                self.upcoming_tiles[y][x] = Status::Empty;
-            }
+    // A fingerprint of everything that decides how the game goes from here,
+    // for two copies of a game to check that they still agree
+    pub fn state_hash(&self) -> u64 {
+        let mut bytes: Vec<u8> = self.rows.iter().flat_map(|row| row.to_le_bytes()).collect();
+        match &self.active_tetromino {
+            Some(mino) => bytes.extend([mino.tr_type as u8, mino.orientation as u8]),
+            None => bytes.push(u8::MAX),
         }
+        bytes.extend(self.x.to_le_bytes());
+        bytes.extend(self.y.to_le_bytes());
+        bytes.push(self.held_piece.map_or(u8::MAX, |mino| mino as u8));
+        bytes.push(self.held as u8);
+        bytes.extend(self.upcoming.iter().map(|mino| *mino as u8));
+        for count in [
+            self.pieces_placed,
+            self.lines_cleared,
+            self.stats.score,
+            self.stats.attack,
+        ] {
+            bytes.extend((count as u64).to_le_bytes());
+        }
+        bytes.push(self.topped_out as u8);
+        fnv1a(&bytes)
     }
 
-    fn clear_held(&mut self) {
-        for y in 0..4 {
-            for x in 0..4 {
-                self.held_tiles[y][x] = Status::Empty;
-            }
-        }
+    pub fn seed(&self) -> u64 {
+        self.seed
     }
 
-    fn draw_upcoming(&mut self) {
-        for (index, mino) in self.upcoming.iter().enumerate() {
-            let at = Tetromino::new(*mino);
+    pub fn upcoming(&self) -> &[TetrominoType] {
+        &self.upcoming
+    }
 
-            if index > 4 {
-                break;
-            }
+    // Whether the locked stack covers (x, y); the active piece never counts
+    pub fn is_filled(&self, x: usize, y: usize) -> bool {
+        self.rows[y] & (1 << x) != 0
+    }
 
-            match at.piece_data {
-                PieceData::Small(data) => {
-                    draw_piece!(
-                        self.upcoming_tiles,
-                        data,
-                        3,
-                        3,
-                        false,
-                        at.tr_type,
-                        0,
-                        index as i32 * 4,
-                        at.orientation,
-                        false
-                    );
-                }
-                PieceData::Medium(data) => {
-                    draw_piece!(
-                        self.upcoming_tiles,
-                        data,
-                        4,
-                        3,
-                        false,
-                        at.tr_type,
-                        0,
-                        index as i32 * 4,
-                        at.orientation,
-                        false
-                    );
-                }
-                PieceData::Large(data) => {
-                    draw_piece!(
-                        self.upcoming_tiles,
-                        data,
-                        4,
-                        4,
-                        false,
-                        at.tr_type,
-                        0,
-                        index as i32 * 4,
-                        at.orientation,
-                        false
-                    );
-                }
-            }
+    fn full_row(&self) -> u16 {
+        ((1u32 << self.width) - 1) as u16
+    }
+
+    pub fn active(&self) -> Option<&Tetromino> {
+        self.active_tetromino.as_ref()
+    }
+
+    // Where the active piece entered the field
+    pub fn spawn(&self) -> (i32, i32) {
+        self.spawn
+    }
+
+    // Where the active piece is now, after any moves and gravity
+    pub fn active_at(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+
+    pub fn held_piece(&self) -> Option<TetrominoType> {
+        self.held_piece
+    }
+
+    pub fn hold_used(&self) -> bool {
+        self.held
+    }
+
+    // None once a fixed sequence has run out
+    fn next_piece(&mut self) -> Option<TetrominoType> {
+        if self.upcoming.is_empty() {
+            return None;
         }
+        let mino = self.upcoming.remove(0);
+
+        if self.upcoming.len() < 7 && !self.fixed_sequence {
+            self.upcoming.extend(gen_bag(&mut self.rng));
+        }
+
+        Some(mino)
     }
 
-    fn draw_held(&mut self) {
-        if let Some(mino) = self.held_piece {
-            let at = Tetromino::new(mino);
+    // Whether a fixed sequence has been played out, leaving nothing to place
+    pub fn out_of_pieces(&self) -> bool {
+        self.active_tetromino.is_none() && self.upcoming.is_empty()
+    }
 
-            match at.piece_data {
-                PieceData::Small(data) => {
-                    draw_piece!(
-                        self.held_tiles,
-                        data,
-                        3,
-                        3,
-                        false,
-                        at.tr_type,
-                        0,
-                        0,
-                        at.orientation,
-                        false
-                    );
-                }
-                PieceData::Medium(data) => {
-                    draw_piece!(
-                        self.held_tiles,
-                        data,
-                        4,
-                        3,
-                        false,
-                        at.tr_type,
-                        0,
-                        0,
-                        at.orientation,
-                        false
-                    );
-                }
-                PieceData::Large(data) => {
-                    draw_piece!(
-                        self.held_tiles,
-                        data,
-                        4,
-                        4,
-                        false,
-                        at.tr_type,
-                        0,
-                        0,
-                        at.orientation,
-                        false
-                    );
-                }
+    fn clear_lines(&mut self) -> usize {
+        let full = self.full_row();
+        let lines: Vec<usize> = (0..self.height).filter(|&y| self.rows[y] == full).collect();
+        self.lines_cleared += lines.len();
+
+        for &line in &lines {
+            for y in (4..=line).rev() {
+                self.tiles[y] = self.tiles[y - 1].clone();
+                self.rows[y] = self.rows[y - 1];
+                self.garbage[y] = self.garbage[y - 1];
             }
         }
+
+        lines.len()
     }
 
-    fn clear_lines(&mut self) {
-        let mut lines = Vec::new();
+    // Pushes the stack up by a garbage row for each hole column given, bottom
+    // row last. The active piece is lifted if the stack rises into it, and
+    // anything pushed off the top of the buffer tops the game out.
+    pub fn add_garbage(&mut self, holes: &[usize]) {
+        self.clear();
 
-        for y in 0..self.height {
-            let mut full = true;
-            for x in 0..self.width {
-                if self.tiles[y][x] == Status::Empty {
-                    full = false;
-                    break;
-                }
+        for &hole in holes {
+            if self.rows[0] != 0 && self.can_top_out {
+                self.end(TopOut::Garbage);
             }
 
-            if full {
-                lines.push(y);
-                self.lines_cleared += 1;
+            self.tiles.remove(0);
+            self.rows.remove(0);
+            self.garbage.remove(0);
+
+            self.tiles.push(
+                (0..self.width)
+                    .map(|x| {
+                        if x == hole {
+                            Status::Empty
+                        } else {
+                            Status::Garbage
+                        }
+                    })
+                    .collect(),
+            );
+            self.rows.push(self.full_row() & !(1 << hole));
+            self.garbage.push(self.full_row() & !(1 << hole));
+        }
+
+        if let Some(mino) = self.active_tetromino.clone() {
+            while self.y > 0 && self.collides(&mino, self.x, self.y) {
+                self.y -= 1;
+            }
+
+            // Nowhere left to lift it to
+            if self.collides(&mino, self.x, self.y) && self.can_top_out {
+                self.end(TopOut::Garbage);
+                return;
             }
         }
 
-        for line in lines {
-            for y in (4..=line).rev() {
-                for x in 0..self.width {
-                    self.tiles[y][x] = self.tiles[y - 1][x];
-                    self.col_buffer[y][x] = self.col_buffer[y - 1][x];
+        self.draw();
+    }
+
+    // Rows that still hold any garbage
+    pub fn garbage_rows(&self) -> usize {
+        self.garbage.iter().filter(|row| **row != 0).count()
+    }
+
+    // Drops everything above `row` down by one, leaving an empty row on top
+    fn remove_row(&mut self, row: usize) {
+        self.tiles.remove(row);
+        self.tiles.insert(0, vec![Status::Empty; self.width]);
+        self.rows.remove(row);
+        self.rows.insert(0, 0);
+        self.garbage.remove(row);
+        self.garbage.insert(0, 0);
+    }
+
+    pub fn position(&self) -> Position {
+        let field = self
+            .tiles
+            .iter()
+            .zip(&self.rows)
+            .map(|(tiles, locked)| {
+                tiles
+                    .iter()
+                    .enumerate()
+                    .map(|(x, tile)| {
+                        if locked & (1 << x) != 0 {
+                            *tile
+                        } else {
+                            Status::Empty
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut position = Position {
+            field,
+            current: self.active_tetromino.as_ref().map(|mino| mino.tr_type),
+            hold: self.held_piece,
+            queue: self.upcoming.clone(),
+        };
+        position.trim();
+        position
+    }
+
+    // Sets up the field and pieces of `position` before the first piece
+    // spawns. Once its queue runs out the pieces come from the usual bags.
+    pub fn load_position(&mut self, position: &Position) {
+        for row in self.tiles.iter_mut() {
+            row.fill(Status::Empty);
+        }
+        self.rows.fill(0);
+        self.garbage.fill(0);
+
+        let rows = position.field.iter().rev().take(self.height);
+        for (depth, row) in rows.enumerate() {
+            let y = self.height - 1 - depth;
+            for (x, cell) in row.iter().enumerate().take(self.width) {
+                if let Status::FillType(_) | Status::Garbage = cell {
+                    self.tiles[y][x] = *cell;
+                    self.rows[y] |= 1 << x;
+                }
+                if *cell == Status::Garbage {
+                    self.garbage[y] |= 1 << x;
                 }
             }
         }
+
+        self.active_tetromino = None;
+        self.held_piece = position.hold;
+        self.upcoming = position.current.into_iter().collect();
+        self.upcoming.extend(&position.queue);
+        while self.upcoming.len() < 7 && !self.fixed_sequence {
+            self.upcoming.extend(gen_bag(&mut self.rng));
+        }
     }
 
-    pub fn new_tetromino(&mut self) {
-        let at = Tetromino::new(self.upcoming.remove(0));
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            tiles: self.tiles.clone(),
+            rows: self.rows.clone(),
+            garbage: self.garbage.clone(),
+            active_tetromino: self.active_tetromino.clone(),
+            x: self.x,
+            y: self.y,
+            spawn: self.spawn,
+            upcoming: self.upcoming.clone(),
+            rng: self.rng.clone(),
+            held_piece: self.held_piece,
+            held: self.held,
+            pieces_placed: self.pieces_placed,
+            lines_cleared: self.lines_cleared,
+            stats: self.stats.clone(),
+            finesse: self.finesse.clone(),
+        }
+    }
 
-        self.clear_upcoming();
-        self.draw_upcoming();
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.tiles = snapshot.tiles;
+        self.rows = snapshot.rows;
+        self.garbage = snapshot.garbage;
+        self.active_tetromino = snapshot.active_tetromino;
+        self.x = snapshot.x;
+        self.y = snapshot.y;
+        self.spawn = snapshot.spawn;
+        self.upcoming = snapshot.upcoming;
+        self.rng = snapshot.rng;
+        self.held_piece = snapshot.held_piece;
+        self.held = snapshot.held;
+        self.pieces_placed = snapshot.pieces_placed;
+        self.lines_cleared = snapshot.lines_cleared;
+        self.stats = snapshot.stats;
+        self.finesse = snapshot.finesse;
+
+        self.topped_out = false;
+        self.top_out = None;
+        self.piece_inputs = 0;
+        self.last_rotated = false;
+        self.lock_delay_timer = None;
+        self.gravity_timer = self.now;
+    }
 
-        if self.upcoming.len() < 7 {
-            self.upcoming.extend(gen_bag());
-        }
+    pub fn new_tetromino(&mut self) {
+        let Some(mino) = self.next_piece() else {
+            self.active_tetromino = None;
+            return;
+        };
+        let at = Tetromino::new(mino);
 
         self.x = (self.width / 2 - 2) as i32;
         self.y = 10;
         self.active_tetromino = Some(at);
 
-        if self.check_loss() {
-            self.tiles = vec![vec![Status::Empty; self.width]; self.height];
-            self.col_buffer = vec![vec![false; self.width]; self.height];
-            self.upcoming = gen_bag();
-            self.upcoming.extend(gen_bag());
-            self.held_piece = None;
-            self.held = false;
-            self.clear_upcoming();
-            self.clear_held();
-            self.active_tetromino = Some(Tetromino::new(self.upcoming.remove(0)));
-            self.x = (self.width / 2 - 2) as i32;
-            self.y = 10;
+        if self.check_loss() && self.can_top_out {
+            self.end(TopOut::BlockOut);
         } else {
-            self.move_tetromino((0, 1));
+            while self.check_loss() {
+                self.remove_row(self.height - 1);
+            }
+            self.shift((0, 1));
         }
+
+        self.spawn = (self.x, self.y);
+        self.piece_inputs = 0;
+        self.last_rotated = false;
     }
 
     pub fn rotate(&mut self, dir: RotationDirection) {
-        let original = self.active_tetromino.as_ref().unwrap().clone();
-        let mut mino = self.active_tetromino.as_mut().unwrap().clone();
-
-        let table_entry = match dir {
-            RotationDirection::Clockwise => match mino.orientation {
-                0 => 0,
-                1 => 2,
-                2 => 4,
-                _ => 6,
-            },
-            RotationDirection::CounterClockwise => match mino.orientation {
-                0 => 7,
-                1 => 1,
-                2 => 3,
-                _ => 5,
-            },
-        };
-
-        mino.orientation = match dir {
-            RotationDirection::Clockwise => (mino.orientation + 1) % 4,
-            RotationDirection::CounterClockwise => (mino.orientation + 3) % 4,
-        };
-
-        let kick_table = match mino.piece_data {
-            PieceData::Small(_) => SMALL_MINO_KICK_TABLE,
-            PieceData::Medium(_) => SMALL_MINO_KICK_TABLE,
-            PieceData::Large(_) => LARGE_MINO_KICK_TABLE,
+        let Some(mino) = self.active_tetromino.clone() else {
+            return;
         };
+        self.count_input();
 
         self.clear();
 
-        let mut pass = false;
-
-        for &(x, y) in &kick_table[table_entry][..5] {
-            if !self.collision_check_buffer(&mino, (x as i32, y as i32)) {
-                self.x += x as i32;
-                self.y += y as i32;
-                self.active_tetromino = Some(mino.clone());
-                pass = true;
-                if mino.tr_type == TetrominoType::T {
-                    if x != 0 && y != 0 {
-                        self.draw();
-                    }
-                }
-
-                break;
-            }
-        }
-
-        if !pass {
-            self.active_tetromino = Some(original);
+        if let Some((rotated, x, y)) = self.kick(&mino, self.x, self.y, dir) {
+            // The last SRS test for T is the only 1x2 kick, and it always
+            // upgrades a mini to a full T-spin
+            self.last_kick_far = (x - self.x).abs() == 1 && (y - self.y).abs() == 2;
+            self.last_rotated = true;
+            self.x = x;
+            self.y = y;
+            self.active_tetromino = Some(rotated);
         }
 
         if self.lock_delay_cur < self.lock_delay_max {
@@ -372,83 +689,128 @@ impl Board {
     }
 
     fn collision_check_buffer(&self, mino: &Tetromino, offset: (i32, i32)) -> bool {
-        match mino.piece_data {
-            PieceData::Small(data) => check_col!(self, data[mino.orientation], 3, 3, offset),
-            PieceData::Medium(data) => check_col!(self, data[mino.orientation], 4, 3, offset),
-            PieceData::Large(data) => check_col!(self, data[mino.orientation], 4, 4, offset),
-        }
-
-        false
+        self.collides(mino, self.x + offset.0, self.y + offset.1)
     }
 
     pub fn soft_harddrop(&mut self) {
         if let Some(ref mut mino) = self.active_tetromino.clone() {
+            self.count_input();
             self.clear();
 
+            let from = self.y;
             loop {
                 if self.collision_check_buffer(mino, (0, 1)) {
                     break;
                 }
                 self.y += 1;
+                self.last_rotated = false;
             }
+            self.stats.record_drop((self.y - from) as usize, false);
             self.draw();
         }
     }
 
+    fn count_input(&mut self) {
+        self.piece_inputs += 1;
+        self.stats.keys += 1;
+    }
+
+    fn detect_tspin(&self) -> TSpin {
+        match &self.active_tetromino {
+            Some(mino) if self.last_rotated => self.tspin(mino, self.x, self.y, self.last_kick_far),
+            _ => TSpin::None,
+        }
+    }
+
+    fn lock_and_spawn(&mut self) {
+        self.grade_finesse();
+        self.settle();
+    }
+
+    // Locks the active piece where it stands, clears lines and deals the next
+    fn settle(&mut self) {
+        self.held = false;
+
+        let tspin = self.detect_tspin();
+        self.lock_piece();
+        self.draw();
+
+        let lines = self.clear_lines();
+        let perfect = self.rows.iter().all(|row| *row == 0);
+        self.stats.record_clear(lines, tspin, perfect, self.level);
+
+        self.new_tetromino();
+    }
+
+    // Grades the piece about to lock against the fewest inputs that could have
+    // placed it, on the stack as it stands without the piece.
+    fn grade_finesse(&mut self) {
+        if let Some(mino) = &self.active_tetromino {
+            let target = movegen::placement_cells(mino, self.x, self.y);
+            let spawned = Tetromino::new(mino.tr_type);
+
+            if let Some(optimal) = finesse::optimal_inputs(self, &spawned, self.spawn, &target) {
+                self.finesse.graded += 1;
+
+                if self.piece_inputs > optimal.len() {
+                    self.finesse.faults += 1;
+                    self.finesse.last_fault = Some(optimal);
+                }
+            }
+        }
+
+        self.piece_inputs = 0;
+    }
+
     fn lock_piece(&mut self) {
         if let Some(tetromino) = &self.active_tetromino {
-            match tetromino.piece_data {
-                PieceData::Small(data) => {
-                    for row in 0..3 {
-                        for col in 0..3 {
-                            if data[tetromino.orientation][row][col] {
-                                self.col_buffer[(row as i32 + self.y) as usize]
-                                    [(col as i32 + self.x) as usize] = true;
-                            }
-                        }
-                    }
-                }
-                PieceData::Medium(data) => {
-                    for row in 0..3 {
-                        for col in 0..4 {
-                            if data[tetromino.orientation][row][col] {
-                                self.col_buffer[(row as i32 + self.y) as usize]
-                                    [(col as i32 + self.x) as usize] = true;
-                            }
-                        }
-                    }
-                }
-                PieceData::Large(data) => {
-                    for row in 0..4 {
-                        for col in 0..4 {
-                            if data[tetromino.orientation][row][col] {
-                                self.col_buffer[(row as i32 + self.y) as usize]
-                                    [(col as i32 + self.x) as usize] = true;
-                            }
-                        }
+            for (row, mask) in tetromino.row_masks().iter().enumerate() {
+                if let Some(mask) = shift_mask(*mask, self.x, self.width) {
+                    if mask != 0 {
+                        self.rows[(self.y + row as i32) as usize] |= mask;
                     }
                 }
             }
+            self.stats.record_piece(tetromino.tr_type);
             self.pieces_placed += 1;
         }
     }
 
+    // Locks the active piece at `placement` straight away, as if its path had
+    // been played, for searches trying placements on a copy of the board.
+    // Finesse is not graded.
+    pub fn place(&mut self, placement: &Placement) {
+        if self.active_tetromino.is_none() {
+            return;
+        }
+        self.clear();
+
+        self.active_tetromino = Some(placement.mino.clone());
+        self.x = placement.x;
+        self.y = placement.y;
+        self.last_rotated = placement.spin;
+        self.last_kick_far = false;
+        self.stats.keys += placement.path.len();
+
+        self.settle();
+    }
+
     pub fn hard_drop(&mut self) {
         if let Some(ref mut mino) = self.active_tetromino.clone() {
+            self.count_input();
             self.clear();
 
+            let from = self.y;
             loop {
                 if self.collision_check_buffer(mino, (0, 1)) {
                     break;
                 }
                 self.y += 1;
+                self.last_rotated = false;
             }
+            self.stats.record_drop((self.y - from) as usize, true);
 
-            self.held = false;
-            self.lock_piece();
-            self.draw();
-            self.clear_lines();
-            self.new_tetromino();
+            self.lock_and_spawn();
         }
     }
 
@@ -461,11 +823,17 @@ impl Board {
     }
 
     pub fn move_tetromino(&mut self, offset: (i32, i32)) {
+        self.count_input();
+        self.shift(offset);
+    }
+
+    fn shift(&mut self, offset: (i32, i32)) {
         if let Some(ref mut mino) = self.active_tetromino.clone() {
             self.clear();
             if !self.collision_check_buffer(mino, offset) {
-                self.x = self.x + offset.0;
-                self.y = self.y + offset.1;
+                self.x += offset.0;
+                self.y += offset.1;
+                self.last_rotated = false;
                 if self.lock_delay_cur < self.lock_delay_max {
                     self.lock_delay_cur += Duration::from_millis(500);
                 }
@@ -474,6 +842,44 @@ impl Board {
         }
     }
 
+    // Marks `cells` as the target for a piece of `kind`, replacing any
+    // previous target. Only empty tiles take the overlay, so the active piece
+    // and its ghost show on top of it.
+    pub fn set_overlay(&mut self, kind: TetrominoType, cells: &[(usize, usize)]) {
+        self.clear_overlay();
+        self.mark(Status::Overlay(kind), cells);
+    }
+
+    pub fn clear_overlay(&mut self) {
+        self.unmark(|tile| matches!(tile, Status::Overlay(_)));
+    }
+
+    // Outlines a suggested placement the same way, replacing any other
+    pub fn set_hint(&mut self, kind: TetrominoType, cells: &[(usize, usize)]) {
+        self.clear_hint();
+        self.mark(Status::Hint(kind), cells);
+    }
+
+    pub fn clear_hint(&mut self) {
+        self.unmark(|tile| matches!(tile, Status::Hint(_)));
+    }
+
+    fn mark(&mut self, status: Status, cells: &[(usize, usize)]) {
+        for &(x, y) in cells {
+            if self.tiles[y][x] == Status::Empty {
+                self.tiles[y][x] = status;
+            }
+        }
+    }
+
+    fn unmark(&mut self, marked: impl Fn(&Status) -> bool) {
+        for tile in self.tiles.iter_mut().flatten() {
+            if marked(tile) {
+                *tile = Status::Empty;
+            }
+        }
+    }
+
     pub fn clear_all_ghosts(&mut self) {
         for row in self.y as usize..self.height {
             for col in 0..self.width {
@@ -486,17 +892,19 @@ impl Board {
     }
 
     pub fn hold_piece(&mut self) {
-        if self.held {
+        let Some(mut at) = self.active_tetromino.clone() else {
+            return;
+        };
+        if self.held || (self.held_piece.is_none() && self.upcoming.is_empty()) {
             return;
         }
 
         self.held = true;
+        self.stats.keys += 1;
+        self.stats.holds += 1;
 
         let mut held = self.held_piece;
-        let mut at = self.active_tetromino.clone().unwrap();
 
-        self.clear_held();
-        self.clear_upcoming();
         self.clear();
 
         if let Some(h) = held {
@@ -504,15 +912,16 @@ impl Board {
             at = Tetromino::new(h);
         } else {
             held = Some(at.tr_type);
-            at = Tetromino::new(self.upcoming.remove(0));
+            at = Tetromino::new(self.next_piece().unwrap());
         }
 
         self.held_piece = held;
         self.active_tetromino = Some(at);
         self.x = (self.width / 2 - 2) as i32;
         self.y = 10;
-        self.draw_held();
-        self.draw_upcoming();
+        self.spawn = (self.x, self.y);
+        self.piece_inputs = 0;
+        self.last_rotated = false;
         self.draw();
     }
 
@@ -617,48 +1026,104 @@ impl Board {
         }
     }
 
+    pub fn gravity(&self) -> Duration {
+        self.gravity_interval
+    }
+
+    // Time for the active piece to fall one row
+    pub fn set_gravity(&mut self, interval: Duration) {
+        self.gravity_interval = interval;
+    }
+
+    // At high levels the piece falls more than one row between frames, so
+    // every interval that has passed moves it down a row.
     fn apply_gravity(&mut self) {
-        if self.gravity_timer.elapsed() >= self.gravity_interval {
-            self.gravity_timer = Instant::now();
+        let elapsed = self.now.saturating_sub(self.gravity_timer);
+        if elapsed < self.gravity_interval {
+            return;
+        }
 
-            self.clear();
+        let rows = (elapsed.as_nanos() / self.gravity_interval.as_nanos().max(1)) as usize;
+        self.gravity_timer = self.now;
 
-            if !self.collision_check_buffer(self.active_tetromino.as_ref().unwrap(), (0, 1)) {
-                self.y += 1;
-                self.lock_delay_timer = None;
-                self.lock_delay_cur = Duration::from_millis(500);
-            }
+        self.clear();
 
-            self.draw();
+        for _ in 0..rows.min(self.height) {
+            if self.collision_check_buffer(self.active_tetromino.as_ref().unwrap(), (0, 1)) {
+                break;
+            }
+            self.y += 1;
+            self.last_rotated = false;
+            self.lock_delay_timer = None;
+            self.lock_delay_cur = Duration::from_millis(500);
         }
+
+        self.draw();
     }
 
     fn handle_lock_delay(&mut self) {
         if let Some(mino) = self.active_tetromino.as_ref() {
-            if self.collision_check_buffer(&mino, (0, 1)) {
+            if self.collision_check_buffer(mino, (0, 1)) {
                 if self.lock_delay_timer.is_none() {
-                    self.lock_delay_timer = Some(Instant::now());
+                    self.lock_delay_timer = Some(self.now);
                     self.lock_delay_cur = self.lock_delay_interval;
                 }
 
                 if let Some(timer) = self.lock_delay_timer {
-                    if timer.elapsed() >= self.lock_delay_cur {
+                    if self.now.saturating_sub(timer) >= self.lock_delay_cur {
                         self.lock_delay_timer = None;
                         self.lock_delay_cur = self.lock_delay_interval;
 
-                        self.held = false;
-                        self.lock_piece();
-                        self.draw();
-                        self.clear_lines();
-                        self.new_tetromino();
+                        self.lock_and_spawn();
                     }
                 }
             }
         }
     }
 
-    pub fn update(&mut self) {
+    // Moves the game on to `now`, game time since it started. Gravity and the
+    // lock delay only ever see this clock, so a game stepped through the same
+    // times with the same inputs plays out the same way.
+    pub fn update(&mut self, now: Duration) {
+        self.now = now;
+        if self.active_tetromino.is_none() {
+            return;
+        }
         self.apply_gravity();
-        self.handle_lock_delay();
+        if self.auto_lock {
+            self.handle_lock_delay();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(text: &str) -> Board {
+        let mut board = Board::with_seed((10, 20), 1);
+        board.load_position(&Position::from_text(text).unwrap());
+        board.new_tetromino();
+        board
+    }
+
+    #[test]
+    fn garbage_rows_follow_the_stack() {
+        let mut board = board("current I\nqueue -\nfield\nSS........\nGGG....GGG\n");
+        assert_eq!(board.garbage_rows(), 1);
+
+        board.add_garbage(&[0, 4]);
+        assert_eq!(board.garbage_rows(), 3);
+
+        let snapshot = board.snapshot();
+        board.add_garbage(&[2]);
+        assert_eq!(board.garbage_rows(), 4);
+        board.restore(snapshot);
+        assert_eq!(board.garbage_rows(), 3);
+
+        // The I fills the gap in the loaded row, clearing it
+        board.hard_drop();
+        assert_eq!(board.lines_cleared, 1);
+        assert_eq!(board.garbage_rows(), 2);
     }
 }
@@ -0,0 +1,766 @@
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::board::{shift_mask, Board, RotationDirection, Stack};
+use crate::mino::{Tetromino, TetrominoType};
+use crate::movegen::{self, Move, Placement};
+use crate::settings::Settings;
+use crate::stats::{self, Stats, TSpin};
+use crate::tbp::Tbp;
+
+// Speeds the bot can be set to play at, in pieces per second
+pub const BOT_PPS: [f64; 6] = [0.5, 1.0, 2.0, 3.0, 5.0, 10.0];
+
+// Pieces searched past the current one, through the hold and next queue
+const LOOKAHEAD: usize = 2;
+// Boards kept at each step of the search
+const BEAM_WIDTH: usize = 8;
+
+// How much each feature of a board counts for or against it. Every feature
+// grows with how bad or good the board is, so the signs say which is which.
+// Any left out of a JSON set of weights keep their defaults.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Weights {
+    // Empty cells with something above them
+    pub holes: f64,
+    // Height differences between neighbouring columns
+    pub bumpiness: f64,
+    // All column heights added up
+    pub height: f64,
+    // Rows past half the field, which get dangerous quickly
+    pub danger: f64,
+    // Depth of every well but the deepest, which is kept for tetrises
+    pub wells: f64,
+    // Open slots a T can spin into for a double
+    pub tslots: f64,
+    // Garbage sent by the placements on the way
+    pub attack: f64,
+    // Lines cleared without sending anything
+    pub burns: f64,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            holes: -4.0,
+            bumpiness: -0.35,
+            height: -0.1,
+            danger: -1.5,
+            wells: -0.4,
+            tslots: 1.5,
+            attack: 2.0,
+            burns: -2.0,
+        }
+    }
+}
+
+fn filled(rows: &[u16], x: usize, y: usize) -> bool {
+    rows[y] & (1 << x) != 0
+}
+
+// Heights, and the empty cells with something above them, going down the
+// rows once
+fn columns(stack: &impl Stack) -> (Vec<usize>, usize) {
+    let rows = stack.rows();
+    let mut heights = vec![0; stack.width()];
+    let mut covered = 0u16;
+    let mut holes = 0;
+
+    for (y, row) in rows.iter().enumerate() {
+        let mut tops = row & !covered;
+        while tops != 0 {
+            let x = tops.trailing_zeros() as usize;
+            heights[x] = rows.len() - y;
+            tops &= tops - 1;
+        }
+        holes += (covered & !row).count_ones() as usize;
+        covered |= row;
+    }
+    (heights, holes)
+}
+
+// Wells are columns lower than both neighbours, with the walls counting as
+// full height
+fn wells(heights: &[usize]) -> usize {
+    let mut depths: Vec<usize> = (0..heights.len())
+        .map(|x| {
+            let left = if x == 0 { usize::MAX } else { heights[x - 1] };
+            let right = heights.get(x + 1).copied().unwrap_or(usize::MAX);
+            left.min(right).saturating_sub(heights[x])
+        })
+        .collect();
+
+    depths.sort_unstable();
+    depths.pop();
+    depths.iter().sum()
+}
+
+// Spots a T pointing down fits into with both corners under it filled and
+// one above it, so it can only get in with a spin
+fn tslots(stack: &impl Stack) -> usize {
+    let (rows, width) = (stack.rows(), stack.width() as i32);
+    let filled = |x: i32, y: i32| {
+        x < 0
+            || x >= width
+            || y >= rows.len() as i32
+            || (y >= 0 && filled(rows, x as usize, y as usize))
+    };
+
+    // Nothing above the stack has a floor
+    let top = rows.iter().position(|row| *row != 0).unwrap_or(rows.len());
+    let mut slots = 0;
+    for y in (top as i32 - 2).max(1)..rows.len() as i32 - 1 {
+        for x in 0..width - 2 {
+            let open = [(x, y), (x + 1, y), (x + 2, y), (x + 1, y + 1)]
+                .iter()
+                .all(|&(cx, cy)| !filled(cx, cy));
+            let floor = filled(x, y + 1) && filled(x + 2, y + 1) && filled(x + 1, y + 2);
+            let roof = filled(x, y - 1) != filled(x + 2, y - 1);
+
+            if open && floor && roof {
+                slots += 1;
+            }
+        }
+    }
+    slots
+}
+
+pub fn evaluate(stack: &impl Stack, weights: &Weights) -> f64 {
+    let (heights, holes) = columns(stack);
+    // The lowest column is left out, so keeping a well open costs nothing
+    let well = (0..heights.len()).min_by_key(|&x| heights[x]).unwrap_or(0);
+    let bumpiness: usize = heights
+        .iter()
+        .enumerate()
+        .filter(|&(x, _)| x != well)
+        .map(|(_, height)| *height)
+        .collect::<Vec<_>>()
+        .windows(2)
+        .map(|pair| pair[0].abs_diff(pair[1]))
+        .sum();
+    let half = (stack.rows().len() - 10) / 2;
+    let danger: usize = heights
+        .iter()
+        .map(|height| height.saturating_sub(half))
+        .sum();
+
+    weights.holes * holes as f64
+        + weights.bumpiness * bumpiness as f64
+        + weights.height * heights.iter().sum::<usize>() as f64
+        + weights.danger * danger as f64
+        + weights.wells * wells(&heights) as f64
+        + weights.tslots * tslots(stack) as f64
+}
+
+// A placement for the active piece, after holding first if `hold` is set
+#[derive(Clone)]
+pub struct Plan {
+    pub hold: bool,
+    pub placement: Placement,
+    pub score: f64,
+}
+
+impl Plan {
+    // Whether both put the same piece in the same cells
+    fn same_move(&self, other: &Plan) -> bool {
+        self.hold == other.hold && self.placement.cells() == other.placement.cells()
+    }
+}
+
+// A board partway through the search: the stack and the pieces, and none of
+// the drawing or timing a whole board carries
+#[derive(Clone)]
+struct State {
+    width: usize,
+    rows: Vec<u16>,
+    active: Option<TetrominoType>,
+    spawn: (i32, i32),
+    hold: Option<TetrominoType>,
+    // Whether the active piece came out of the hold
+    held: bool,
+    // How far into the board's queue the search has got
+    next: usize,
+    // For the combo and back to back the next clear carries on
+    stats: Stats,
+}
+
+impl Stack for State {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn rows(&self) -> &[u16] {
+        &self.rows
+    }
+}
+
+impl State {
+    fn new(board: &Board) -> Self {
+        State {
+            width: board.width,
+            rows: board.rows().to_vec(),
+            active: board.active().map(|mino| mino.tr_type),
+            spawn: board.spawn(),
+            hold: board.held_piece(),
+            held: board.hold_used(),
+            next: 0,
+            stats: board.stats.clone(),
+        }
+    }
+
+    // Brings out the next piece where the board would: a row lower when
+    // there is room. False when it has nowhere to go, which tops the game
+    // out. A queue that has run out leaves nothing to place.
+    fn deal(&mut self, queue: &[TetrominoType]) -> bool {
+        self.held = false;
+        let Some(&mino) = queue.get(self.next) else {
+            self.active = None;
+            return true;
+        };
+        self.next += 1;
+
+        let (x, y) = ((self.width / 2 - 2) as i32, 10);
+        let piece = Tetromino::new(mino);
+        if self.collides(&piece, x, y) {
+            return false;
+        }
+        let y = if self.collides(&piece, x, y + 1) {
+            y
+        } else {
+            y + 1
+        };
+
+        self.active = Some(mino);
+        self.spawn = (x, y);
+        true
+    }
+
+    // Swaps the active piece into the hold, like the board does. False when
+    // it cannot.
+    fn hold(&mut self, queue: &[TetrominoType]) -> bool {
+        let Some(active) = self.active else {
+            return false;
+        };
+        if self.held || self.hold == Some(active) {
+            return false;
+        }
+
+        let out = match self.hold {
+            Some(held) => held,
+            None => match queue.get(self.next) {
+                Some(next) => {
+                    self.next += 1;
+                    *next
+                }
+                // Nothing left in the queue to bring out
+                None => return false,
+            },
+        };
+
+        self.hold = Some(active);
+        self.active = Some(out);
+        self.spawn = ((self.width / 2 - 2) as i32, 10);
+        self.held = true;
+        true
+    }
+
+    // Locks `placement` and deals the next piece, returning the lines cleared
+    // and the garbage they send. None when the next piece tops out.
+    fn place(&mut self, placement: &Placement, queue: &[TetrominoType]) -> Option<(usize, usize)> {
+        let tspin = if placement.spin {
+            self.tspin(&placement.mino, placement.x, placement.y, false)
+        } else {
+            TSpin::None
+        };
+
+        for (row, mask) in placement.mino.row_masks().iter().enumerate() {
+            if let Some(mask) = shift_mask(*mask, placement.x, self.width) {
+                if mask != 0 {
+                    self.rows[(placement.y + row as i32) as usize] |= mask;
+                }
+            }
+        }
+
+        let full = ((1u32 << self.width) - 1) as u16;
+        let height = self.rows.len();
+        self.rows.retain(|row| *row != full);
+        let lines = height - self.rows.len();
+        self.rows.splice(0..0, std::iter::repeat_n(0, lines));
+
+        let perfect = self.rows.iter().all(|row| *row == 0);
+        let clear = self.stats.record_clear(lines, tspin, perfect, 1);
+
+        self.deal(queue).then(|| (lines, stats::attack(&clear)))
+    }
+}
+
+struct Node {
+    state: State,
+    // Which of the search's distinct first moves led here, None at the root
+    first: Option<usize>,
+    // Reward collected along the way, before the board itself is scored
+    reward: f64,
+    score: f64,
+}
+
+// Every way to place the next piece from `node`: the active piece, and
+// whatever holding would bring out instead. Each child comes with the move
+// that made it.
+fn expand(node: &Node, queue: &[TetrominoType], weights: &Weights) -> Vec<(bool, Placement, Node)> {
+    let mut children = Vec::new();
+    for hold in [false, true] {
+        let mut state = node.state.clone();
+        if hold && !state.hold(queue) {
+            continue;
+        }
+        let Some(active) = state.active else {
+            continue;
+        };
+
+        let mino = Tetromino::new(active);
+        for placement in movegen::placements(&state, &mino, state.spawn, &movegen::ALL_MOVES) {
+            let mut after = state.clone();
+            let Some((lines, attack)) = after.place(&placement, queue) else {
+                continue;
+            };
+
+            let burned = if attack == 0 { lines } else { 0 };
+            let reward =
+                node.reward + weights.attack * attack as f64 + weights.burns * burned as f64;
+
+            let child = Node {
+                score: reward + evaluate(&after, weights),
+                first: node.first,
+                state: after,
+                reward,
+            };
+            children.push((hold, placement, child));
+        }
+    }
+    children
+}
+
+// The best placement for the active piece, found with a beam search through
+// the hold piece and the next queue. None when every placement tops out or
+// there is no piece to place.
+pub fn plan(board: &Board, weights: &Weights) -> Option<Plan> {
+    ranked(board, weights, 1).pop()
+}
+
+// Up to `count` placements for the active piece, best first. Placements that
+// lead to the boards the search kept longest come first, then the ones it
+// dropped along the way, in the order they were dropped.
+pub fn ranked(board: &Board, weights: &Weights, count: usize) -> Vec<Plan> {
+    let queue = board.upcoming();
+    let mut beam = vec![Node {
+        state: State::new(board),
+        first: None,
+        reward: 0.0,
+        score: 0.0,
+    }];
+    // The distinct first moves, and the ones still ranked with their scores
+    let mut firsts: Vec<Plan> = Vec::new();
+    let mut plans: Vec<(usize, f64)> = Vec::new();
+
+    for _ in 0..=LOOKAHEAD {
+        let mut children: Vec<_> = beam
+            .iter()
+            .flat_map(|node| expand(node, queue, weights))
+            .collect();
+        if children.is_empty() {
+            break;
+        }
+        children.sort_by(|a, b| b.2.score.total_cmp(&a.2.score));
+
+        let children: Vec<Node> = children
+            .into_iter()
+            .map(|(hold, placement, mut child)| {
+                if child.first.is_none() {
+                    let plan = Plan {
+                        hold,
+                        placement,
+                        score: child.score,
+                    };
+                    let index = firsts.iter().position(|other| other.same_move(&plan));
+                    child.first = Some(index.unwrap_or_else(|| {
+                        firsts.push(plan);
+                        firsts.len() - 1
+                    }));
+                }
+                child
+            })
+            .collect();
+
+        let mut ranked = vec![false; firsts.len()];
+        let reached = children
+            .iter()
+            .filter_map(|child| Some((child.first?, child.score)));
+        plans = reached
+            .chain(plans)
+            .filter(|(index, _)| !std::mem::replace(&mut ranked[*index], true))
+            .collect();
+
+        beam = children;
+        beam.truncate(BEAM_WIDTH);
+    }
+
+    plans
+        .into_iter()
+        .take(count)
+        .map(|(index, score)| Plan {
+            score,
+            ..firsts[index].clone()
+        })
+        .collect()
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Action {
+    Hold,
+    Input(Move),
+}
+
+impl Action {
+    fn press(self, board: &mut Board) {
+        match self {
+            Action::Hold => board.hold_piece(),
+            Action::Input(Move::Left) => board.move_tetromino((-1, 0)),
+            Action::Input(Move::Right) => board.move_tetromino((1, 0)),
+            Action::Input(Move::RotateCW) => board.rotate(RotationDirection::Clockwise),
+            Action::Input(Move::RotateCCW) => board.rotate(RotationDirection::CounterClockwise),
+            Action::Input(Move::SoftDrop) => board.soft_harddrop(),
+            Action::Input(Move::HardDrop) => board.hard_drop(),
+        }
+    }
+}
+
+fn actions(plan: &Plan) -> Vec<Action> {
+    plan.hold
+        .then_some(Action::Hold)
+        .into_iter()
+        .chain(plan.placement.path.iter().map(|mv| Action::Input(*mv)))
+        .collect()
+}
+
+// Presses every key of `plan` at once, for running the bot without a clock
+pub fn play(board: &mut Board, plan: &Plan) {
+    for action in actions(plan) {
+        action.press(board);
+    }
+}
+
+// Where the bot's moves come from
+enum Brain {
+    // The built in search, which takes a while and so runs in the background
+    // while the game keeps going
+    Heuristic {
+        weights: Weights,
+        thinking: Option<Receiver<Option<Plan>>>,
+    },
+    External(Tbp),
+}
+
+// Plays the board it is given, choosing each placement with its brain and
+// pressing the keys for it at a steady pace.
+pub struct Bot {
+    brain: Brain,
+    pps: f64,
+    inputs: VecDeque<(Duration, Action)>,
+    // Where the keys are taking the active piece
+    target: Option<Placement>,
+    // When the next piece may start, in game time
+    next_piece: Duration,
+    // Pieces placed as of the last frame
+    placed: usize,
+    last: Option<Action>,
+}
+
+impl Bot {
+    pub fn new(pps: f64) -> Self {
+        Self::with_weights(Weights::default(), pps)
+    }
+
+    // The built in bot judging boards by `weights` rather than its own
+    pub fn with_weights(weights: Weights, pps: f64) -> Self {
+        Self::with_brain(
+            Brain::Heuristic {
+                weights,
+                thinking: None,
+            },
+            pps,
+        )
+    }
+
+    // Plays the moves of the TBP bot at `path` rather than its own
+    pub fn external(path: &Path, pps: f64) -> Self {
+        Self::with_brain(Brain::External(Tbp::spawn(path)), pps)
+    }
+
+    // The external bot given on the command line if there is one, otherwise
+    // the built in one
+    pub fn from_settings(settings: &Settings) -> Self {
+        match &settings.external_bot {
+            Some(path) => Self::external(path, settings.bot_pps),
+            None => Self::new(settings.bot_pps),
+        }
+    }
+
+    fn with_brain(brain: Brain, pps: f64) -> Self {
+        Bot {
+            brain,
+            pps,
+            inputs: VecDeque::new(),
+            target: None,
+            next_piece: Duration::ZERO,
+            placed: 0,
+            last: None,
+        }
+    }
+
+    pub fn pps(&self) -> f64 {
+        self.pps
+    }
+
+    pub fn name(&self) -> String {
+        match &self.brain {
+            Brain::Heuristic { .. } => "built in".to_string(),
+            Brain::External(tbp) => tbp.name(),
+        }
+    }
+
+    // A plan for the active piece once the brain has come up with one
+    fn think(&mut self, board: &Board) -> Option<Option<Plan>> {
+        let (weights, thinking) = match &mut self.brain {
+            Brain::Heuristic { weights, thinking } => (weights, thinking),
+            Brain::External(tbp) => return tbp.think(board),
+        };
+
+        let Some(receiver) = thinking else {
+            let (tx, rx) = mpsc::channel();
+            let (board, weights) = (board.clone(), *weights);
+            thread::spawn(move || tx.send(plan(&board, &weights)).ok());
+
+            *thinking = Some(rx);
+            return None;
+        };
+
+        match receiver.try_recv() {
+            Ok(plan) => {
+                *thinking = None;
+                Some(plan)
+            }
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                *thinking = None;
+                None
+            }
+        }
+    }
+
+    // Waits for the brain's plan for the active piece, for games without a
+    // clock. None when nothing fits or an external bot has failed, which
+    // includes taking too long to answer.
+    pub fn decide(&mut self, board: &Board) -> Option<Plan> {
+        let tbp = match &mut self.brain {
+            Brain::Heuristic { weights, .. } => return plan(board, weights),
+            Brain::External(tbp) => tbp,
+        };
+
+        loop {
+            if let Some(plan) = tbp.think(board) {
+                return plan;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    // What went wrong with an external bot, if it has failed
+    pub fn error(&self) -> Option<&str> {
+        match &self.brain {
+            Brain::Heuristic { .. } => None,
+            Brain::External(tbp) => tbp.error(),
+        }
+    }
+
+    // Forgets a plan still being worked out for a piece that is gone
+    fn forget(&mut self) {
+        if let Brain::Heuristic { thinking, .. } = &mut self.brain {
+            *thinking = None;
+        }
+    }
+
+    // The keys to press now in place of `action`. The plan was made with the
+    // piece where it was then, and gravity may have moved it since, so the
+    // rest of the way is worked out again from where it is now. Any keys the
+    // schedule has no room left for go in at once.
+    fn catch_up(&self, board: &Board, action: Action) -> Vec<Action> {
+        let Action::Input(planned) = action else {
+            return vec![action];
+        };
+
+        let route = self
+            .target
+            .as_ref()
+            .and_then(|target| movegen::route(board, target))
+            .unwrap_or_else(|| vec![planned]);
+        let now = route.len().saturating_sub(self.inputs.len()).max(1);
+        route[..now].iter().map(|mv| Action::Input(*mv)).collect()
+    }
+
+    // Called every frame with the game time. Each piece gets an even share of
+    // a second at the bot's PPS, its keys spread across it and the hard drop
+    // landing at the end.
+    pub fn update(&mut self, board: &mut Board, elapsed: Duration) {
+        // Gravity or the lock delay got there first, so the plan no longer
+        // fits the board
+        if board.pieces_placed != self.placed {
+            self.inputs.clear();
+            self.forget();
+            self.placed = board.pieces_placed;
+        }
+
+        if self.inputs.is_empty() {
+            if board.active().is_none() {
+                return;
+            }
+
+            match self.think(board) {
+                Some(Some(plan)) => {
+                    let actions = actions(&plan);
+                    let interval = Duration::from_secs_f64(1.0 / self.pps);
+                    let start = elapsed.max(self.next_piece);
+
+                    for (index, action) in actions.iter().enumerate() {
+                        let share = (index + 1) as f64 / actions.len() as f64;
+                        self.inputs
+                            .push_back((start + interval.mul_f64(share), *action));
+                    }
+                    self.next_piece = start + interval;
+                    self.target = Some(plan.placement);
+                    self.last = None;
+                }
+                // Nothing fits, so drop it where it is and let the game end
+                Some(None) => board.hard_drop(),
+                None => return,
+            }
+        }
+
+        while let Some(&(at, action)) = self.inputs.front() {
+            // Once the piece is tucked under the stack the lock delay is
+            // running, so the keys after a soft drop go in at once
+            let tucked = matches!(self.last, Some(Action::Input(Move::SoftDrop)));
+            if at > elapsed && !tucked {
+                break;
+            }
+
+            self.inputs.pop_front();
+            for action in self.catch_up(board, action) {
+                action.press(board);
+                self.last = Some(action);
+            }
+
+            // A route shorter than planned drops the piece early, and the
+            // keys left over were never meant for the next one
+            if board.pieces_placed != self.placed {
+                self.inputs.clear();
+                break;
+            }
+        }
+
+        self.placed = board.pieces_placed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::Position;
+
+    fn board(field: &str, current: TetrominoType) -> Board {
+        let text = format!(
+            "hold -\ncurrent {}\nqueue -\nfield\n{}",
+            current.letter(),
+            field
+        );
+        let mut board = Board::with_seed((10, 20), 0);
+        board.load_position(&Position::from_text(&text).unwrap());
+        board.new_tetromino();
+        board
+    }
+
+    #[test]
+    fn covering_a_hole_scores_worse() {
+        let weights = Weights::default();
+        let open = board("..........\nGGGG.GGGGG\n", TetrominoType::T);
+        let covered = board("....G.....\nGGGG.GGGGG\n", TetrominoType::T);
+
+        assert!(evaluate(&open, &weights) > evaluate(&covered, &weights));
+    }
+
+    #[test]
+    fn plans_the_tetris() {
+        let well = "GGGGGGGGG.\n".repeat(4);
+        let board = board(&well, TetrominoType::I);
+        let floor = board.height as i32 - 1;
+
+        let plan = plan(&board, &Weights::default()).expect("no plan");
+        assert!(!plan.hold);
+        assert_eq!(
+            plan.placement.cells(),
+            (floor - 3..=floor).map(|y| (9, y)).collect::<Vec<_>>()
+        );
+    }
+
+    // The plan is made with the piece where it spawned, but by the time the
+    // keys go in it has fallen onto the stack, where the rotation kicks it
+    // somewhere else and the rest of the keys have to change to match
+    #[test]
+    fn keys_follow_the_piece_after_it_falls() {
+        let mut board = board("GGGG......\nGGGG...GGG\nGGGG..GGGG\n", TetrominoType::T);
+        let floor = board.height as i32 - 1;
+        let slot = vec![
+            (3, floor - 5),
+            (3, floor - 4),
+            (3, floor - 3),
+            (4, floor - 4),
+        ];
+        let placement = movegen::active_placements(&board)
+            .into_iter()
+            .find(|placement| placement.cells() == slot)
+            .expect("no path to the slot");
+        let plan = Plan {
+            hold: false,
+            placement,
+            score: 0.0,
+        };
+
+        let mut expected = board.clone();
+        play(&mut expected, &plan);
+
+        let (tx, rx) = mpsc::channel();
+        tx.send(Some(plan)).unwrap();
+        let mut bot = Bot::with_brain(
+            Brain::Heuristic {
+                weights: Weights::default(),
+                thinking: Some(rx),
+            },
+            1.0,
+        );
+
+        for _ in 0..board.height {
+            board.move_tetromino((0, 1));
+        }
+        let mut elapsed = Duration::ZERO;
+        while board.pieces_placed == 0 {
+            bot.update(&mut board, elapsed);
+            elapsed += Duration::from_millis(50);
+        }
+        assert_eq!(board.position().field, expected.position().field);
+    }
+}
@@ -0,0 +1,381 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use crate::board::Status;
+use crate::bot::{Bot, Weights};
+use crate::dashboard::Dashboard;
+use crate::fumen::{self, Page};
+use crate::mode::GameMode;
+use crate::net::{Connection, DEFAULT_PORT};
+use crate::pc::{self, Placement, Rules, MAX_PC_ROWS};
+use crate::position::Position;
+use crate::records::Records;
+use crate::settings::Settings;
+use crate::sim::{self, MAX_PIECES, MODES};
+
+pub const USAGE: &str = "\
+usage: tetrs [command]
+
+commands:
+    (none)              play
+    --fumen <fumen>     play Zen from a fumen (v115@...)
+    --position <file>   play Zen from a position file
+    --bot <path>        use a Tetris Bot Protocol bot in place of the
+                        built in one; combines with the options above.
+                        A bot that takes over 5s to answer has failed
+    stats [--json]      print long-term statistics
+    pc-solve --fumen <fumen> [--lines <n>] [--no-hold]
+                        print every perfect clear of the fumen's field
+                        and #Q=[hold](current)queue pieces, clearing
+                        n lines (4 by default, up to 6)
+    host [--port <n>]   wait for someone to join a game of versus,
+                        on port 7420 unless told otherwise
+    join <addr>         join a game of versus hosted at addr, given
+                        as host or host:port
+    sim [--mode <mode>] [--games <n>] [--seed <n>] [--pps <n>]
+        [--bot <path> | --weights <json>] [--settings <file>]
+        [--max-pieces <n>]
+                        play games with the bot and no terminal, one
+                        per seed counting up from --seed (0), printing
+                        a JSON line of results for each. Modes are
+                        marathon (the default), sprint, ultra, zen, dig
+                        and survival, set up by the settings file given
+                        or the game's own. Weights are a JSON object of
+                        the built in bot's. Games stop after 10000
+                        pieces unless told otherwise";
+
+pub struct SimOptions {
+    pub mode: GameMode,
+    pub games: u64,
+    pub seed: u64,
+    pub pps: Option<f64>,
+    pub bot: Option<PathBuf>,
+    pub weights: Weights,
+    pub settings: Option<PathBuf>,
+    pub max_pieces: usize,
+}
+
+// Where a game opened from the command line starts
+pub enum Start {
+    Fumen(String),
+    File(PathBuf),
+}
+
+pub enum Command {
+    Play {
+        start: Option<Start>,
+        bot: Option<PathBuf>,
+    },
+    Stats {
+        json: bool,
+    },
+    PcSolve {
+        fumen: String,
+        rules: Rules,
+    },
+    Sim(SimOptions),
+    Host {
+        port: u16,
+    },
+    Join {
+        addr: String,
+    },
+}
+
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        ["stats"] => Ok(Command::Stats { json: false }),
+        ["stats", "--json"] => Ok(Command::Stats { json: true }),
+        ["pc-solve", rest @ ..] => parse_pc_solve(rest),
+        ["sim", rest @ ..] => parse_sim(rest),
+        ["host"] => Ok(Command::Host { port: DEFAULT_PORT }),
+        ["host", "--port", port] => port
+            .parse()
+            .map(|port| Command::Host { port })
+            .map_err(|_| "--port takes a number up to 65535".to_string()),
+        ["join", addr] => Ok(Command::Join {
+            addr: with_port(addr),
+        }),
+        _ => parse_play(&args),
+    }
+}
+
+// The default port unless the address names one
+fn with_port(addr: &str) -> String {
+    match addr.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_ok() => addr.to_string(),
+        _ => format!("{}:{}", addr, DEFAULT_PORT),
+    }
+}
+
+fn parse_play(args: &[&str]) -> Result<Command, String> {
+    let mut start = None;
+    let mut bot = None;
+
+    for pair in args.chunks(2) {
+        let value = pair.get(1).copied();
+        match (pair[0], value) {
+            ("--fumen", Some(fumen)) if start.is_none() => {
+                start = Some(Start::Fumen(fumen.to_string()));
+            }
+            ("--position", Some(path)) if start.is_none() => {
+                start = Some(Start::File(PathBuf::from(path)));
+            }
+            ("--bot", Some(path)) if bot.is_none() => bot = Some(PathBuf::from(path)),
+            ("--fumen" | "--position" | "--bot", None) => {
+                return Err(format!("'{}' needs a value", pair[0]));
+            }
+            (other, _) => return Err(format!("unrecognised arguments starting at '{}'", other)),
+        }
+    }
+
+    // Caught here, before the terminal is taken over, rather than as a
+    // failed bot in the middle of a game
+    if let Some(path) = &bot {
+        if !path.is_file() {
+            return Err(format!("no bot at '{}'", path.display()));
+        }
+    }
+
+    Ok(Command::Play { start, bot })
+}
+
+fn parse_pc_solve(args: &[&str]) -> Result<Command, String> {
+    let mut fumen = None;
+    let mut rules = Rules::default();
+
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        match arg {
+            "--no-hold" => rules.hold = false,
+            "--fumen" | "--lines" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("'{}' needs a value", arg))?;
+                if arg == "--fumen" {
+                    fumen = Some(value.to_string());
+                } else {
+                    rules.rows = value
+                        .parse()
+                        .ok()
+                        .filter(|rows| (1..=MAX_PC_ROWS).contains(rows))
+                        .ok_or_else(|| format!("--lines takes 1 to {}", MAX_PC_ROWS))?;
+                }
+            }
+            other => return Err(format!("unrecognised argument '{}'", other)),
+        }
+    }
+
+    let fumen = fumen.ok_or_else(|| "pc-solve needs --fumen".to_string())?;
+    Ok(Command::PcSolve { fumen, rules })
+}
+
+fn parse_sim(args: &[&str]) -> Result<Command, String> {
+    let mut options = SimOptions {
+        mode: GameMode::Marathon,
+        games: 1,
+        seed: 0,
+        pps: None,
+        bot: None,
+        weights: Weights::default(),
+        settings: None,
+        max_pieces: MAX_PIECES,
+    };
+    let mut weighted = false;
+
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        let value = *args
+            .next()
+            .ok_or_else(|| format!("'{}' needs a value", arg))?;
+        let number = || format!("{} takes a number", arg);
+
+        match arg {
+            "--mode" => {
+                options.mode = MODES
+                    .into_iter()
+                    .find(|mode| mode.name().eq_ignore_ascii_case(value))
+                    .ok_or_else(|| format!("no mode '{}' to simulate", value))?;
+            }
+            "--games" => options.games = value.parse().map_err(|_| number())?,
+            "--seed" => options.seed = value.parse().map_err(|_| number())?,
+            "--pps" => {
+                options.pps = Some(
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|pps: &f64| *pps > 0.0 && pps.is_finite())
+                        .ok_or_else(|| "--pps takes a positive number".to_string())?,
+                );
+            }
+            "--max-pieces" => options.max_pieces = value.parse().map_err(|_| number())?,
+            "--bot" => options.bot = Some(PathBuf::from(value)),
+            "--weights" => {
+                options.weights =
+                    serde_json::from_str(value).map_err(|err| format!("bad --weights: {}", err))?;
+                weighted = true;
+            }
+            "--settings" => options.settings = Some(PathBuf::from(value)),
+            other => return Err(format!("unrecognised argument '{}'", other)),
+        }
+    }
+
+    if let Some(path) = &options.bot {
+        if weighted {
+            return Err("--weights are for the built in bot, not --bot".to_string());
+        }
+        if !path.is_file() {
+            return Err(format!("no bot at '{}'", path.display()));
+        }
+    }
+
+    Ok(Command::Sim(options))
+}
+
+// Output piped into something like `head` that stops reading early is not
+// an error worth reporting.
+fn ignore_broken_pipe(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        other => other,
+    }
+}
+
+pub fn stats(json: bool) -> io::Result<()> {
+    let dashboard = Dashboard::new(&Records::load()?);
+    let mut out = io::stdout().lock();
+
+    let written = if json {
+        serde_json::to_writer_pretty(&mut out, &dashboard)
+            .map_err(io::Error::from)
+            .and_then(|_| writeln!(out))
+    } else {
+        dashboard
+            .lines()
+            .iter()
+            .try_for_each(|line| writeln!(out, "{}", line))
+    };
+
+    ignore_broken_pipe(written)
+}
+
+// Only the first page of a multi-page fumen is played
+pub fn load_start(start: &Start) -> io::Result<Position> {
+    let invalid = |err: String| io::Error::new(io::ErrorKind::InvalidData, err);
+
+    match start {
+        Start::Fumen(text) => {
+            let pages = fumen::decode(text).map_err(invalid)?;
+            Ok(pages[0].to_position())
+        }
+        Start::File(path) => Position::from_text(&fs::read_to_string(path)?).map_err(invalid),
+    }
+}
+
+// One page per piece, each showing the field just after it goes down
+fn solution_fumen(position: &Position, solution: &[Placement]) -> String {
+    let width = position.field[0].len();
+    // Bottom row first, the way placements count them
+    let mut rows: Vec<Vec<Status>> = position.field.iter().rev().cloned().collect();
+    let mut pages = Vec::new();
+
+    for placement in solution {
+        for &(x, row) in &placement.cells {
+            if rows.len() <= row {
+                rows.resize(row + 1, vec![Status::Empty; width]);
+            }
+            rows[row][x] = Status::FillType(placement.kind);
+        }
+
+        pages.push(Page::from_position(&Position {
+            field: rows.iter().rev().cloned().collect(),
+            current: None,
+            hold: None,
+            queue: Vec::new(),
+        }));
+        rows.retain(|row| row.contains(&Status::Empty));
+    }
+
+    fumen::encode(&pages)
+}
+
+// Prints each solution as its pieces in order and a fumen of it, as soon as
+// the solver finds it
+pub fn pc_solve(text: &str, rules: Rules) -> io::Result<()> {
+    let invalid = |err: String| io::Error::new(io::ErrorKind::InvalidData, err);
+
+    let position = fumen::decode(text).map_err(invalid)?[0].to_position();
+    if position.current.is_none() && position.queue.is_empty() {
+        return Err(invalid(
+            "the fumen has no pieces, add a #Q=[hold](current)queue comment".to_string(),
+        ));
+    }
+
+    let mut out = io::stdout().lock();
+    let mut count = 0;
+    let written = pc::solve_all(&position, rules, |solution| {
+        count += 1;
+        let pieces: String = solution
+            .iter()
+            .map(|placement| placement.kind.letter())
+            .collect();
+        writeln!(out, "{} {}", pieces, solution_fumen(&position, solution))
+    });
+
+    if written.is_ok() && count == 0 {
+        eprintln!("tetrs: no perfect clear found");
+    }
+    ignore_broken_pipe(written)
+}
+
+// Plays each game in turn, printing its results as soon as it ends
+pub fn sim(options: &SimOptions) -> io::Result<()> {
+    let settings = match &options.settings {
+        Some(path) => Settings::load_from(path)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))?,
+        None => Settings::load(),
+    };
+    let pps = options.pps.unwrap_or(settings.bot_pps);
+    let mut bot = match &options.bot {
+        Some(path) => Bot::external(path, pps),
+        None => Bot::with_weights(options.weights, pps),
+    };
+
+    let mut out = io::stdout().lock();
+    for game in 0..options.games {
+        let seed = options.seed.wrapping_add(game);
+        let result = sim::run(
+            options.mode,
+            &settings,
+            &mut bot,
+            seed,
+            pps,
+            options.max_pieces,
+        )
+        .map_err(io::Error::other)?;
+
+        let written = serde_json::to_writer(&mut out, &result)
+            .map_err(io::Error::from)
+            .and_then(|_| writeln!(out))
+            .and_then(|_| out.flush());
+        if let Err(err) = written {
+            return ignore_broken_pipe(Err(err));
+        }
+    }
+    Ok(())
+}
+
+// Both of these wait for the other side before the terminal is taken over,
+// saying what they are waiting for
+pub fn host(port: u16) -> io::Result<(Connection, u64)> {
+    eprintln!("waiting for an opponent on port {}...", port);
+    Connection::host(port)
+}
+
+pub fn join(addr: &str) -> io::Result<(Connection, u64)> {
+    eprintln!("joining {}...", addr);
+    Connection::join(addr)
+}
@@ -8,6 +8,7 @@ pub const BLUE_TILE: &str = "\x1b[34m██\x1b[0m";
 pub const CYAN_TILE: &str = "\x1b[36m██\x1b[0m";
 pub const ORANGE_TILE: &str = "\x1b[38;5;214m██\x1b[0m";
 pub const MAGENTA_TILE: &str = "\x1b[35m██\x1b[0m";
+pub const GRAY_TILE: &str = "\x1b[90m██\x1b[0m";
 
 pub const RED_GHOST: &str = "\x1b[31m░░\x1b[0m";
 pub const GREEN_GHOST: &str = "\x1b[32m░░\x1b[0m";
@@ -17,5 +18,23 @@ pub const CYAN_GHOST: &str = "\x1b[36m░░\x1b[0m";
 pub const ORANGE_GHOST: &str = "\x1b[33m░░\x1b[0m";
 pub const MAGENTA_GHOST: &str = "\x1b[35m░░\x1b[0m";
 
+pub const RED_OVERLAY: &str = "\x1b[2;31m▒▒\x1b[0m";
+pub const GREEN_OVERLAY: &str = "\x1b[2;32m▒▒\x1b[0m";
+pub const YELLOW_OVERLAY: &str = "\x1b[2;33m▒▒\x1b[0m";
+pub const BLUE_OVERLAY: &str = "\x1b[2;34m▒▒\x1b[0m";
+pub const CYAN_OVERLAY: &str = "\x1b[2;36m▒▒\x1b[0m";
+pub const ORANGE_OVERLAY: &str = "\x1b[2;38;5;214m▒▒\x1b[0m";
+pub const MAGENTA_OVERLAY: &str = "\x1b[2;35m▒▒\x1b[0m";
+
+pub const RED_HINT: &str = "\x1b[31m[]\x1b[0m";
+pub const GREEN_HINT: &str = "\x1b[32m[]\x1b[0m";
+pub const YELLOW_HINT: &str = "\x1b[33m[]\x1b[0m";
+pub const BLUE_HINT: &str = "\x1b[34m[]\x1b[0m";
+pub const CYAN_HINT: &str = "\x1b[36m[]\x1b[0m";
+pub const ORANGE_HINT: &str = "\x1b[38;5;214m[]\x1b[0m";
+pub const MAGENTA_HINT: &str = "\x1b[35m[]\x1b[0m";
+
+pub const MAX_NEXT_QUEUE: usize = 7;
+
 pub const DAS: usize = 133;
 pub const ARR: usize = 0;
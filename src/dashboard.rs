@@ -0,0 +1,197 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::mode::GameMode;
+use crate::records::{GameRecord, Records};
+use crate::util::{format_date, format_hours, format_time};
+
+const SPARK_BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+// How many points the in-terminal charts keep, newest last
+const SPARK_WIDTH: usize = 24;
+
+const DAY_SECS: u64 = 86_400;
+
+// Unix time starts on a Thursday, so shifting by three days makes weeks run
+// Monday to Sunday.
+fn week_of(date: u64) -> u64 {
+    (date / DAY_SECS + 3) / 7
+}
+
+fn week_start(week: u64) -> u64 {
+    (week * 7).saturating_sub(3) * DAY_SECS
+}
+
+#[derive(Serialize)]
+pub struct WeekSummary {
+    // Monday the week starts on, as YYYY-MM-DD
+    pub week: String,
+    pub games: usize,
+    pub pieces: usize,
+    pub lines: usize,
+    pub time_played_ms: u64,
+    pub average_pps: f64,
+    pub best_pps: f64,
+    // None when no placement that week could be graded
+    pub finesse_accuracy: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct SprintPoint {
+    pub date: String,
+    pub time_ms: u64,
+}
+
+// Totals and trends over every recorded game, shared by the statistics
+// screen and `tetrs stats`.
+#[derive(Serialize)]
+pub struct Dashboard {
+    pub games: usize,
+    pub pieces: usize,
+    pub lines: usize,
+    pub time_played_ms: u64,
+    pub weeks: Vec<WeekSummary>,
+    pub sprint_times: Vec<SprintPoint>,
+}
+
+fn summarize_week(week: u64, games: &[&GameRecord]) -> WeekSummary {
+    let graded: usize = games.iter().map(|game| game.finesse_graded).sum();
+    let faults: usize = games.iter().map(|game| game.finesse_faults).sum();
+
+    WeekSummary {
+        week: format_date(week_start(week)),
+        games: games.len(),
+        pieces: games.iter().map(|game| game.pieces).sum(),
+        lines: games.iter().map(|game| game.lines).sum(),
+        time_played_ms: games.iter().map(|game| game.time_ms).sum(),
+        average_pps: games.iter().map(|game| game.pps).sum::<f64>() / games.len() as f64,
+        best_pps: games.iter().map(|game| game.pps).fold(0.0, f64::max),
+        finesse_accuracy: (graded > 0)
+            .then(|| graded.saturating_sub(faults) as f64 / graded as f64 * 100.0),
+    }
+}
+
+impl Dashboard {
+    pub fn new(records: &Records) -> Self {
+        let mut by_week: BTreeMap<u64, Vec<&GameRecord>> = BTreeMap::new();
+        for game in &records.games {
+            by_week.entry(week_of(game.date)).or_default().push(game);
+        }
+
+        let sprint_times = records
+            .games
+            .iter()
+            .filter(|game| game.mode == GameMode::Sprint && game.finished)
+            .map(|game| SprintPoint {
+                date: format_date(game.date),
+                time_ms: game.time_ms,
+            })
+            .collect();
+
+        Dashboard {
+            games: records.games.len(),
+            pieces: records.games.iter().map(|game| game.pieces).sum(),
+            lines: records.games.iter().map(|game| game.lines).sum(),
+            time_played_ms: records.games.iter().map(|game| game.time_ms).sum(),
+            weeks: by_week
+                .iter()
+                .map(|(week, games)| summarize_week(*week, games))
+                .collect(),
+            sprint_times,
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        if self.games == 0 {
+            return vec!["No games recorded yet.".to_string()];
+        }
+
+        let mut lines = vec![
+            format!("{:<16}{:>12}", "Games", self.games),
+            format!(
+                "{:<16}{:>12}",
+                "Time played",
+                format_hours(Duration::from_millis(self.time_played_ms))
+            ),
+            format!("{:<16}{:>12}", "Pieces", self.pieces),
+            format!("{:<16}{:>12}", "Lines", self.lines),
+            String::new(),
+        ];
+
+        let average: Vec<f64> = self.weeks.iter().map(|week| week.average_pps).collect();
+        let best: Vec<f64> = self.weeks.iter().map(|week| week.best_pps).collect();
+        let latest = self.weeks.last().unwrap();
+
+        lines.push(trend_line(
+            "Avg PPS/week",
+            &average,
+            format!("{:.2}", latest.average_pps),
+        ));
+        lines.push(trend_line(
+            "Best PPS/week",
+            &best,
+            format!("{:.2}", latest.best_pps),
+        ));
+
+        let finesse: Vec<f64> = self
+            .weeks
+            .iter()
+            .filter_map(|week| week.finesse_accuracy)
+            .collect();
+        if let Some(last) = finesse.last() {
+            lines.push(trend_line(
+                "Finesse/week",
+                &finesse,
+                format!("{:.1}%", last),
+            ));
+        }
+
+        let sprints: Vec<f64> = self
+            .sprint_times
+            .iter()
+            .map(|point| point.time_ms as f64)
+            .collect();
+        if let Some(best) = self.sprint_times.iter().map(|point| point.time_ms).min() {
+            lines.push(trend_line(
+                "Sprint times",
+                &sprints,
+                format_time(Duration::from_millis(best)),
+            ));
+        }
+
+        lines
+    }
+}
+
+fn trend_line(label: &str, values: &[f64], summary: String) -> String {
+    format!(
+        "{:<16}{:<width$}  {:>9}",
+        label,
+        sparkline(values),
+        summary,
+        width = SPARK_WIDTH
+    )
+}
+
+// One bar per value, scaled between the smallest and largest of the most
+// recent SPARK_WIDTH values.
+pub fn sparkline(values: &[f64]) -> String {
+    let recent = &values[values.len().saturating_sub(SPARK_WIDTH)..];
+
+    let min = recent.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = recent.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let range = max - min;
+
+    recent
+        .iter()
+        .map(|value| {
+            let level = if range > 0.0 {
+                ((value - min) / range * (SPARK_BARS.len() - 1) as f64).round() as usize
+            } else {
+                SPARK_BARS.len() / 2
+            };
+            SPARK_BARS[level]
+        })
+        .collect()
+}
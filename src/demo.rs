@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use crate::board::Board;
+use crate::bot::Bot;
+use crate::mode::{GameEnd, Marathon, ModeController};
+
+// The bot plays an endless marathon by itself, to watch how it stacks and
+// how long it lasts as the gravity speeds up.
+pub struct Demo {
+    bot: Bot,
+    marathon: Marathon,
+}
+
+impl Demo {
+    pub fn new(bot: Bot) -> Self {
+        Demo {
+            bot,
+            marathon: Marathon::new(1, true),
+        }
+    }
+}
+
+impl ModeController for Demo {
+    fn update(&mut self, board: &mut Board, elapsed: Duration) -> Option<GameEnd> {
+        self.bot.update(board, elapsed);
+        self.marathon.update(board, elapsed)
+    }
+
+    fn hud(&self, board: &Board, elapsed: Duration) -> Vec<String> {
+        let mut hud = vec![
+            format!("BOT:     {:.13}", self.bot.name()),
+            format!("BOT PPS: {}", self.bot.pps()),
+        ];
+        hud.extend(self.marathon.hud(board, elapsed));
+        hud
+    }
+
+    fn plays_itself(&self) -> bool {
+        true
+    }
+}
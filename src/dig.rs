@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use crate::board::Board;
+use crate::garbage::HoleGenerator;
+use crate::mode::{GameEnd, ModeController};
+use crate::stats::per_minute;
+
+pub const DIG_ROWS: [usize; 3] = [10, 18, 100];
+pub const DIG_MESSINESS: [u8; 5] = [0, 25, 50, 75, 100];
+
+// Longer races are fed in as the stack comes down rather than all at once
+const VISIBLE_ROWS: usize = 10;
+
+// Clear every garbage row as fast as possible.
+pub struct Dig {
+    total: usize,
+    // Rows not yet put on the board
+    pending: usize,
+    repeat_holes: bool,
+    messiness: u8,
+    holes: HoleGenerator,
+}
+
+impl Dig {
+    pub fn new(rows: usize, repeat_holes: bool, messiness: u8) -> Self {
+        Dig {
+            total: rows,
+            pending: rows,
+            repeat_holes,
+            messiness,
+            holes: HoleGenerator::new(0, repeat_holes, messiness),
+        }
+    }
+
+    fn refill(&mut self, board: &mut Board) {
+        let missing = VISIBLE_ROWS
+            .saturating_sub(board.garbage_rows())
+            .min(self.pending);
+
+        if missing > 0 {
+            let holes = self.holes.holes(board.width, missing);
+            self.pending -= missing;
+            board.add_garbage(&holes);
+        }
+    }
+
+    fn cleared(&self, board: &Board) -> usize {
+        self.total - self.pending - board.garbage_rows()
+    }
+}
+
+impl ModeController for Dig {
+    // Holes come from the board's seed, so the same seed digs the same field
+    fn setup(&mut self, board: &mut Board) {
+        self.holes = HoleGenerator::new(board.seed(), self.repeat_holes, self.messiness);
+        self.refill(board);
+    }
+
+    fn update(&mut self, board: &mut Board, _elapsed: Duration) -> Option<GameEnd> {
+        if board.topped_out {
+            return Some(GameEnd::ToppedOut);
+        }
+
+        self.refill(board);
+
+        if self.pending == 0 && board.garbage_rows() == 0 {
+            return Some(GameEnd::Finished);
+        }
+
+        None
+    }
+
+    fn hud(&self, board: &Board, elapsed: Duration) -> Vec<String> {
+        vec![
+            format!(
+                "GARBAGE: {}/{}",
+                self.total - self.cleared(board),
+                self.total
+            ),
+            format!("GPM:     {:.1}", per_minute(self.cleared(board), elapsed)),
+        ]
+    }
+
+    fn results(&self, board: &Board, elapsed: Duration) -> Vec<String> {
+        vec![
+            format!("Garbage  {:>10}", self.cleared(board)),
+            format!(
+                "GPM      {:>10.1}",
+                per_minute(self.cleared(board), elapsed)
+            ),
+        ]
+    }
+}
@@ -0,0 +1,46 @@
+use crate::board::Board;
+use crate::mino::Tetromino;
+use crate::movegen::{self, Move};
+
+#[derive(Clone, Default)]
+pub struct FinesseStats {
+    pub graded: usize,
+    pub faults: usize,
+    // What the player should have pressed for the last piece they faulted on
+    pub last_fault: Option<Vec<Move>>,
+}
+
+impl FinesseStats {
+    pub fn accuracy(&self) -> f64 {
+        if self.graded == 0 {
+            return 100.0;
+        }
+        (self.graded - self.faults) as f64 / self.graded as f64 * 100.0
+    }
+}
+
+pub fn sequence_string(moves: &[Move]) -> String {
+    moves
+        .iter()
+        .map(|mv| mv.symbol())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Inputs finesse is graded on. Soft drops are left out, so tucks and spins
+// have no path and go ungraded.
+const FINESSE_MOVES: [Move; 4] = [Move::Left, Move::Right, Move::RotateCW, Move::RotateCCW];
+
+// Fewest inputs that take a freshly spawned `mino` at `spawn` to the placement
+// covering `target`, ending in a hard drop.
+pub fn optimal_inputs(
+    board: &Board,
+    mino: &Tetromino,
+    spawn: (i32, i32),
+    target: &[(i32, i32)],
+) -> Option<Vec<Move>> {
+    movegen::placements(board, mino, spawn, &FINESSE_MOVES)
+        .into_iter()
+        .find(|placement| placement.cells() == target)
+        .map(|placement| placement.path)
+}
@@ -0,0 +1,729 @@
+// Reading and writing fumen, the diagram format used across the Tetris
+// community, in its current v115 revision.
+//
+// A fumen is a list of pages. Each page stores its field as a run-length
+// encoded difference from the previous page, an optional piece, and an
+// optional comment, all packed into a custom base64 alphabet.
+
+use crate::board::Status;
+use crate::mino::TetrominoType;
+use crate::position::{parse_queue, Position};
+
+const PREFIX: &str = "v115@";
+const TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub const FIELD_WIDTH: usize = 10;
+// Rows in the main field, above one row of rising garbage
+pub const FIELD_ROWS: usize = 23;
+const FIELD_CELLS: usize = (FIELD_ROWS + 1) * FIELD_WIDTH;
+
+// Run value of a page whose field is unchanged from the last
+const UNCHANGED: u32 = 8 * FIELD_CELLS as u32 + FIELD_CELLS as u32 - 1;
+const MAX_REPEAT: u32 = 63;
+
+const COMMENT_BASE: u32 = 96;
+const MAX_COMMENT: usize = 4095;
+
+const GARBAGE: u8 = 8;
+
+fn block_code(cell: Status) -> u8 {
+    match cell {
+        Status::FillType(TetrominoType::I) => 1,
+        Status::FillType(TetrominoType::L) => 2,
+        Status::FillType(TetrominoType::O) => 3,
+        Status::FillType(TetrominoType::Z) => 4,
+        Status::FillType(TetrominoType::T) => 5,
+        Status::FillType(TetrominoType::J) => 6,
+        Status::FillType(TetrominoType::S) => 7,
+        Status::Garbage => GARBAGE,
+        Status::Empty | Status::FillGhost(_) | Status::Overlay(_) | Status::Hint(_) => 0,
+    }
+}
+
+fn piece_from_code(code: u8) -> Option<TetrominoType> {
+    match code {
+        1 => Some(TetrominoType::I),
+        2 => Some(TetrominoType::L),
+        3 => Some(TetrominoType::O),
+        4 => Some(TetrominoType::Z),
+        5 => Some(TetrominoType::T),
+        6 => Some(TetrominoType::J),
+        7 => Some(TetrominoType::S),
+        _ => None,
+    }
+}
+
+fn cell_from_code(code: u8) -> Status {
+    match code {
+        GARBAGE => Status::Garbage,
+        code => piece_from_code(code).map_or(Status::Empty, Status::FillType),
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    Reverse,
+    Right,
+    Spawn,
+    Left,
+}
+
+impl Rotation {
+    const ALL: [Rotation; 4] = [
+        Rotation::Reverse,
+        Rotation::Right,
+        Rotation::Spawn,
+        Rotation::Left,
+    ];
+
+    fn code(&self) -> u32 {
+        Rotation::ALL.iter().position(|r| r == self).unwrap() as u32
+    }
+}
+
+// A piece on a page, placed by its rotation centre with y counting up from
+// the bottom row of the main field.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Piece {
+    pub kind: TetrominoType,
+    pub rotation: Rotation,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Piece {
+    pub fn cells(&self) -> [(i32, i32); 4] {
+        let spawn = match self.kind {
+            TetrominoType::I => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+            TetrominoType::T => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+            TetrominoType::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+            TetrominoType::L => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+            TetrominoType::J => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+            TetrominoType::S => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+            TetrominoType::Z => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+        };
+
+        spawn.map(|(x, y)| {
+            let (x, y) = match self.rotation {
+                Rotation::Spawn => (x, y),
+                Rotation::Right => (y, -x),
+                Rotation::Reverse => (-x, -y),
+                Rotation::Left => (-y, x),
+            };
+            (self.x + x, self.y + y)
+        })
+    }
+
+    // The encoded location uses an older centre for some pieces, which is
+    // shifted onto the true rotation centre when reading.
+    fn location_offset(&self) -> (i32, i32) {
+        match (self.kind, self.rotation) {
+            (TetrominoType::O, Rotation::Left) => (1, -1),
+            (TetrominoType::O, Rotation::Reverse) => (1, 0),
+            (TetrominoType::O, Rotation::Spawn) => (0, -1),
+            (TetrominoType::I, Rotation::Reverse) => (1, 0),
+            (TetrominoType::I, Rotation::Left) => (0, -1),
+            (TetrominoType::S, Rotation::Spawn) => (0, -1),
+            (TetrominoType::S, Rotation::Right) => (-1, 0),
+            (TetrominoType::Z, Rotation::Spawn) => (0, -1),
+            (TetrominoType::Z, Rotation::Left) => (1, 0),
+            _ => (0, 0),
+        }
+    }
+}
+
+// Cell codes row by row from the top of the main field, ending with the
+// garbage row under it.
+pub type Field = [u8; FIELD_CELLS];
+
+fn index(x: i32, y: i32) -> Option<usize> {
+    let in_field = (0..FIELD_WIDTH as i32).contains(&x) && (-1..FIELD_ROWS as i32).contains(&y);
+    in_field.then(|| (FIELD_ROWS as i32 - 1 - y) as usize * FIELD_WIDTH + x as usize)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Page {
+    pub field: Field,
+    pub piece: Option<Piece>,
+    pub comment: String,
+    // Whether the piece locks into the field before the next page
+    pub lock: bool,
+    // Whether the garbage row rises into the field after locking
+    pub rise: bool,
+    // Whether the field is mirrored after locking
+    pub mirror: bool,
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Page {
+            field: [0; FIELD_CELLS],
+            piece: None,
+            comment: String::new(),
+            lock: true,
+            rise: false,
+            mirror: false,
+        }
+    }
+}
+
+impl Page {
+    // The field the next page's difference is taken against
+    fn next_field(&self) -> Field {
+        let mut field = self.field;
+        if !self.lock {
+            return field;
+        }
+
+        if let Some(piece) = &self.piece {
+            for (x, y) in piece.cells() {
+                if let Some(index) = index(x, y) {
+                    field[index] = block_code(Status::FillType(piece.kind));
+                }
+            }
+        }
+
+        let main = FIELD_ROWS * FIELD_WIDTH;
+        let mut rows: Vec<&[u8]> = field[..main]
+            .chunks(FIELD_WIDTH)
+            .filter(|row| row.contains(&0))
+            .collect();
+        let cleared = FIELD_ROWS - rows.len();
+        let empty = [0; FIELD_WIDTH];
+        for _ in 0..cleared {
+            rows.insert(0, &empty);
+        }
+
+        let mut next = [0; FIELD_CELLS];
+        for (index, row) in rows.iter().enumerate() {
+            next[index * FIELD_WIDTH..(index + 1) * FIELD_WIDTH].copy_from_slice(row);
+        }
+        next[main..].copy_from_slice(&field[main..]);
+
+        if self.rise {
+            next.copy_within(FIELD_WIDTH.., 0);
+            next[main..].fill(0);
+        }
+
+        if self.mirror {
+            for row in next[..main].chunks_mut(FIELD_WIDTH) {
+                row.reverse();
+            }
+        }
+
+        next
+    }
+
+    pub fn to_position(&self) -> Position {
+        let mut position = Position {
+            field: self.field[..FIELD_ROWS * FIELD_WIDTH]
+                .chunks(FIELD_WIDTH)
+                .map(|row| row.iter().map(|code| cell_from_code(*code)).collect())
+                .collect(),
+            current: self.piece.map(|piece| piece.kind),
+            hold: None,
+            queue: Vec::new(),
+        };
+        position.trim();
+
+        // Quiz comments carry the pieces to play, as #Q=[hold](current)queue
+        if let Some(quiz) = self.comment.strip_prefix("#Q=") {
+            if let Some((hold, current, queue)) = parse_quiz(quiz) {
+                position.hold = hold;
+                position.current = current.or(position.current);
+                position.queue = queue;
+            }
+        }
+
+        position
+    }
+
+    // Rows past the top of the fumen field are dropped.
+    pub fn from_position(position: &Position) -> Self {
+        let mut page = Page::default();
+
+        for (depth, row) in position.field.iter().rev().enumerate().take(FIELD_ROWS) {
+            for (x, cell) in row.iter().enumerate().take(FIELD_WIDTH) {
+                if let Some(index) = index(x as i32, depth as i32) {
+                    page.field[index] = block_code(*cell);
+                }
+            }
+        }
+
+        let letter =
+            |mino: Option<TetrominoType>| mino.map_or(String::new(), |m| m.letter().to_string());
+        let queue: String = position.queue.iter().map(|mino| mino.letter()).collect();
+
+        if position.current.is_some() || position.hold.is_some() || !queue.is_empty() {
+            page.comment = format!(
+                "#Q=[{}]({}){}",
+                letter(position.hold),
+                letter(position.current),
+                queue
+            );
+        }
+        page
+    }
+}
+
+type Quiz = (
+    Option<TetrominoType>,
+    Option<TetrominoType>,
+    Vec<TetrominoType>,
+);
+
+fn parse_quiz(quiz: &str) -> Option<Quiz> {
+    let piece = |text: &str| match text {
+        "" => Some(None),
+        _ => text
+            .chars()
+            .next()
+            .and_then(TetrominoType::from_letter)
+            .map(Some),
+    };
+
+    let (hold, rest) = quiz.strip_prefix('[')?.split_once(']')?;
+    let (current, queue) = rest.strip_prefix('(')?.split_once(')')?;
+    let queue = queue.split(';').next().unwrap_or_default();
+
+    Some((piece(hold)?, piece(current)?, parse_queue(queue).ok()?))
+}
+
+// JavaScript's escape(), which fumen applies to comments before packing them
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for ch in text.chars() {
+        match ch {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '@' | '*' | '_' | '+' | '-' | '.' | '/' => {
+                escaped.push(ch)
+            }
+            ch if (ch as u32) < 0x100 => escaped.push_str(&format!("%{:02X}", ch as u32)),
+            ch => {
+                let mut units = [0; 2];
+                for unit in ch.encode_utf16(&mut units) {
+                    escaped.push_str(&format!("%u{:04X}", unit));
+                }
+            }
+        }
+    }
+    escaped
+}
+
+fn unescape(text: &str) -> String {
+    let mut units = Vec::new();
+    let mut rest = text;
+
+    while let Some(ch) = rest.chars().next() {
+        let hex = |digits: &str| u16::from_str_radix(digits, 16).ok();
+
+        let decoded = match ch {
+            '%' if rest.starts_with("%u") && rest.len() >= 6 => {
+                hex(&rest[2..6]).map(|unit| (unit, 6))
+            }
+            '%' if rest.len() >= 3 => hex(&rest[1..3]).map(|unit| (unit, 3)),
+            _ => None,
+        };
+
+        match decoded {
+            Some((unit, length)) => {
+                units.push(unit);
+                rest = &rest[length..];
+            }
+            None => {
+                let mut buffer = [0; 2];
+                units.extend_from_slice(ch.encode_utf16(&mut buffer));
+                rest = &rest[ch.len_utf8()..];
+            }
+        }
+    }
+
+    String::from_utf16_lossy(&units)
+}
+
+struct Reader {
+    values: Vec<u32>,
+    index: usize,
+}
+
+impl Reader {
+    fn new(data: &str) -> Result<Self, String> {
+        let values = data
+            .bytes()
+            .map(|byte| {
+                TABLE
+                    .iter()
+                    .position(|entry| *entry == byte)
+                    .map(|value| value as u32)
+                    .ok_or_else(|| format!("invalid fumen character '{}'", byte as char))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Reader { values, index: 0 })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.index >= self.values.len()
+    }
+
+    // Little endian, six bits per character
+    fn poll(&mut self, chars: usize) -> Result<u32, String> {
+        let end = self.index + chars;
+        let digits = self
+            .values
+            .get(self.index..end)
+            .ok_or_else(|| "fumen data ends early".to_string())?;
+
+        self.index = end;
+        Ok(digits
+            .iter()
+            .rev()
+            .fold(0, |value, digit| value * 64 + digit))
+    }
+}
+
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn push(&mut self, mut value: u32, chars: usize) {
+        for _ in 0..chars {
+            self.data.push(TABLE[(value % 64) as usize]);
+            value /= 64;
+        }
+    }
+}
+
+// Accepts a bare v115 string or a whole fumen URL.
+pub fn decode(text: &str) -> Result<Vec<Page>, String> {
+    let start = text
+        .find(PREFIX)
+        .ok_or_else(|| "only v115 fumen is supported".to_string())?;
+    let data: String = text[start + PREFIX.len()..]
+        .chars()
+        .filter(|ch| *ch != '?' && !ch.is_whitespace())
+        .collect();
+
+    let mut reader = Reader::new(&data)?;
+    let mut pages: Vec<Page> = Vec::new();
+    let mut previous: Field = [0; FIELD_CELLS];
+    let mut comment = String::new();
+    let mut repeat = 0;
+
+    while !reader.is_empty() {
+        let mut field = previous;
+
+        if repeat > 0 {
+            repeat -= 1;
+        } else {
+            let mut index = 0;
+            let mut changed = true;
+
+            while index < FIELD_CELLS {
+                let run = reader.poll(2)?;
+                let diff = (run / FIELD_CELLS as u32) as i32 - 8;
+                let count = (run % FIELD_CELLS as u32) as usize + 1;
+
+                if run == UNCHANGED {
+                    changed = false;
+                }
+
+                let cells = field
+                    .get_mut(index..index + count)
+                    .ok_or_else(|| "fumen field overflows".to_string())?;
+                for cell in cells {
+                    *cell = u8::try_from(*cell as i32 + diff)
+                        .ok()
+                        .filter(|code| *code <= GARBAGE)
+                        .ok_or_else(|| "invalid fumen block".to_string())?;
+                }
+                index += count;
+            }
+
+            if !changed {
+                repeat = reader.poll(1)?;
+            }
+        }
+
+        let mut action = reader.poll(3)?;
+        let mut flag = |modulo: u32| {
+            let value = action % modulo;
+            action /= modulo;
+            value
+        };
+
+        let kind = flag(8) as u8;
+        let rotation = Rotation::ALL[flag(4) as usize];
+        let location = flag(FIELD_CELLS as u32) as i32;
+        let rise = flag(2) == 1;
+        let mirror = flag(2) == 1;
+        let _colour = flag(2);
+        let has_comment = flag(2) == 1;
+        let lock = flag(2) == 0;
+
+        let piece = piece_from_code(kind).map(|kind| {
+            let mut piece = Piece {
+                kind,
+                rotation,
+                x: location % FIELD_WIDTH as i32,
+                y: FIELD_ROWS as i32 - 1 - location / FIELD_WIDTH as i32,
+            };
+            let (dx, dy) = piece.location_offset();
+            piece.x += dx;
+            piece.y += dy;
+            piece
+        });
+
+        if has_comment {
+            let length = reader.poll(2)? as usize;
+            let mut escaped = String::new();
+
+            while escaped.len() < length {
+                let mut chunk = reader.poll(5)?;
+                for _ in 0..4 {
+                    if escaped.len() < length {
+                        escaped.push(char::from(b' ' + (chunk % COMMENT_BASE) as u8));
+                    }
+                    chunk /= COMMENT_BASE;
+                }
+            }
+            comment = unescape(&escaped);
+        }
+
+        let page = Page {
+            field,
+            piece,
+            comment: comment.clone(),
+            lock,
+            rise,
+            mirror,
+        };
+        previous = page.next_field();
+        pages.push(page);
+    }
+
+    if pages.is_empty() {
+        return Err("fumen has no pages".to_string());
+    }
+    Ok(pages)
+}
+
+pub fn encode(pages: &[Page]) -> String {
+    let mut writer = Writer { data: Vec::new() };
+    let mut previous: Field = [0; FIELD_CELLS];
+    let mut comment = String::new();
+    // Where the count of repeated unchanged fields was written, and its value
+    let mut repeat: Option<(usize, u32)> = None;
+
+    for (number, page) in pages.iter().enumerate() {
+        if page.field == previous {
+            match repeat {
+                Some((at, count)) if count < MAX_REPEAT => {
+                    writer.data[at] = TABLE[count as usize + 1];
+                    repeat = Some((at, count + 1));
+                }
+                _ => {
+                    writer.push(UNCHANGED, 2);
+                    repeat = Some((writer.data.len(), 0));
+                    writer.push(0, 1);
+                }
+            }
+        } else {
+            repeat = None;
+
+            let diffs: Vec<u32> = page
+                .field
+                .iter()
+                .zip(previous.iter())
+                .map(|(now, before)| (*now as i32 - *before as i32 + 8) as u32)
+                .collect();
+
+            let mut start = 0;
+            while start < diffs.len() {
+                let run = diffs[start..]
+                    .iter()
+                    .take_while(|diff| **diff == diffs[start])
+                    .count();
+                writer.push(diffs[start] * FIELD_CELLS as u32 + run as u32 - 1, 2);
+                start += run;
+            }
+        }
+
+        let (kind, rotation, location) = match &page.piece {
+            Some(piece) => {
+                let (dx, dy) = piece.location_offset();
+                let (x, y) = (piece.x - dx, piece.y - dy);
+                let location = (FIELD_ROWS as i32 - 1 - y) * FIELD_WIDTH as i32 + x;
+                (
+                    block_code(Status::FillType(piece.kind)) as u32,
+                    piece.rotation.code(),
+                    location.clamp(0, FIELD_CELLS as i32 - 1) as u32,
+                )
+            }
+            None => (0, Rotation::Reverse.code(), 0),
+        };
+
+        let has_comment = page.comment != comment;
+        let flags = [page.rise, page.mirror, number == 0, has_comment, !page.lock];
+        let flags = flags
+            .iter()
+            .rev()
+            .fold(0, |value, flag| value * 2 + *flag as u32);
+        let action = ((flags * FIELD_CELLS as u32 + location) * 4 + rotation) * 8 + kind;
+        writer.push(action, 3);
+
+        if has_comment {
+            let escaped: Vec<u8> = escape(&page.comment).bytes().take(MAX_COMMENT).collect();
+            writer.push(escaped.len() as u32, 2);
+
+            for chunk in escaped.chunks(4) {
+                let value = chunk.iter().rev().fold(0, |value, byte| {
+                    value * COMMENT_BASE + (*byte - b' ') as u32
+                });
+                writer.push(value, 5);
+            }
+            comment = page.comment.clone();
+        }
+
+        previous = page.next_field();
+    }
+
+    format!("{}{}", PREFIX, String::from_utf8(writer.data).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_rows(rows: &[&str]) -> Vec<String> {
+        rows.iter().map(|row| row.to_string()).collect()
+    }
+
+    fn field_rows(page: &Page) -> Vec<String> {
+        page.to_position()
+            .field
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| match cell {
+                        Status::Empty => '.',
+                        _ => 'G',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn decodes_an_empty_page() {
+        let pages = decode("v115@vhAAgH").unwrap();
+        assert_eq!(pages, vec![Page::default()]);
+    }
+
+    #[test]
+    fn decodes_a_url() {
+        let pages = decode("https://fumen.zui.jp/?v115@vhAAgH").unwrap();
+        assert_eq!(pages, vec![Page::default()]);
+    }
+
+    #[test]
+    fn decodes_a_field() {
+        let pages = decode("v115@9gF8DeF8DeF8DeF8NeAgH").unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(
+            field_rows(&pages[0]),
+            text_rows(&["GGGGGG....", "GGGGGG....", "GGGGGG....", "GGGGGG...."])
+        );
+        assert_eq!(pages[0].piece, None);
+    }
+
+    #[test]
+    fn decodes_a_piece() {
+        let pages = decode("v115@vhAVQJ").unwrap();
+        let piece = pages[0].piece.unwrap();
+        assert_eq!(piece.kind, TetrominoType::T);
+        assert_eq!(piece.rotation, Rotation::Spawn);
+        assert_eq!(piece.cells(), [(4, 0), (3, 0), (5, 0), (4, 1)]);
+    }
+
+    #[test]
+    fn encodes_an_empty_page() {
+        assert_eq!(encode(&[Page::default()]), "v115@vhAAgH");
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut field = [0; FIELD_CELLS];
+        for x in 0..9 {
+            field[index(x, 0).unwrap()] = GARBAGE;
+            field[index(x, -1).unwrap()] = GARBAGE;
+        }
+        field[index(9, 1).unwrap()] = 1;
+
+        let mut pages = vec![Page {
+            field,
+            piece: Some(Piece {
+                kind: TetrominoType::I,
+                rotation: Rotation::Left,
+                x: 9,
+                y: 1,
+            }),
+            comment: "#Q=[S](I)TLJ".to_string(),
+            lock: false,
+            ..Page::default()
+        }];
+        // Unchanged fields, including more than one repeat count holds
+        for number in 0..70 {
+            pages.push(Page {
+                field,
+                comment: format!("page {} \u{e9}\u{1F600}", number),
+                lock: false,
+                ..Page::default()
+            });
+        }
+        pages.push(Page {
+            field,
+            piece: Some(Piece {
+                kind: TetrominoType::O,
+                rotation: Rotation::Reverse,
+                x: 3,
+                y: 5,
+            }),
+            rise: true,
+            mirror: true,
+            ..Page::default()
+        });
+
+        let decoded = decode(&encode(&pages)).unwrap();
+        assert_eq!(decoded, pages);
+
+        let position = decoded[0].to_position();
+        assert_eq!(position.hold, Some(TetrominoType::S));
+        assert_eq!(position.current, Some(TetrominoType::I));
+        assert_eq!(
+            position.queue,
+            vec![TetrominoType::T, TetrominoType::L, TetrominoType::J]
+        );
+    }
+
+    #[test]
+    fn round_trips_a_position() {
+        let position = decode("v115@9gF8DeF8DeF8DeF8NeAgH").unwrap()[0].to_position();
+        let page = Page::from_position(&position);
+        assert_eq!(decode(&encode(&[page])).unwrap()[0].to_position(), position);
+    }
+
+    #[test]
+    fn rejects_bad_fumen() {
+        let error = |text: &str| decode(text).unwrap_err();
+
+        // Runs of 200 and 100 cells, past the end of the field
+        assert_eq!(error("v115@HhjfAgH"), "fumen field overflows");
+        // Every cell lowered from empty
+        assert_eq!(error("v115@vDAgH"), "invalid fumen block");
+        assert_eq!(error("v115@vh!AgH"), "invalid fumen character '!'");
+        assert_eq!(error("v115@vhAAg"), "fumen data ends early");
+        assert_eq!(error("v115@"), "fumen has no pages");
+        assert_eq!(error("v110@vhAAgH"), "only v115 fumen is supported");
+    }
+}
@@ -0,0 +1,520 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossterm::event::KeyEventKind;
+use crossterm::style::Attribute;
+use crossterm::{cursor, execute, style::Print};
+
+use crate::board::{Board, RotationDirection, Status};
+use crate::constants::GRAY_TILE;
+use crate::finesse::sequence_string;
+use crate::hint::Hints;
+use crate::input::InputEvent;
+use crate::mino::{Tetromino, TetrominoType};
+use crate::mode::{self, GameMode, ModeController};
+use crate::movegen::Move;
+use crate::position::{self, Position};
+use crate::records::{self, now_unix, GameRecord};
+use crate::settings::Settings;
+use crate::stats::{per_minute, per_second, Clock, StatEntry};
+use crate::ui::{Quit, Ui};
+use crate::util::{
+    format_time, get_ghost_color, get_hint_color, get_overlay_color, get_tile_color,
+};
+
+const DEBOUNCE: Duration = Duration::from_millis(10);
+const FINESSE_HINT_TIME: Duration = Duration::from_millis(1500);
+// How long short messages like a copied position stay in the HUD
+const NOTICE_TIME: Duration = Duration::from_millis(2000);
+const FREEZE_TIME: Duration = Duration::from_millis(1500);
+
+fn draw_board(ui: &mut Ui, board: &Board, sx: u16, sy: u16) {
+    let stdout = &mut ui.stdout;
+
+    for y in 10..board.height + 1 {
+        execute!(stdout, cursor::MoveTo(sx, sy + y as u16 - 10)).unwrap();
+        for x in 0..board.width + 2 {
+            if (x == 0 || x == board.width + 1) && y == board.height {
+                print!("▀");
+            } else if x == 0 || x == board.width + 1 {
+                print!("█");
+            } else if y == board.height {
+                print!("▀▀");
+            } else {
+                match board.tiles[y][x - 1] {
+                    Status::Empty => print!("  "),
+                    Status::FillType(mino) => print!("{}", get_tile_color(mino)),
+                    Status::FillGhost(mino) => print!("{}", get_ghost_color(mino)),
+                    Status::Garbage => print!("{}", GRAY_TILE),
+                    Status::Overlay(mino) => print!("{}", get_overlay_color(mino)),
+                    Status::Hint(mino) => print!("{}", get_hint_color(mino)),
+                }
+            }
+        }
+        execute!(stdout, Print("\r\n")).unwrap();
+    }
+}
+
+// The stack of a board at half size, a character to a cell and two rows to
+// a line
+fn draw_small_board(ui: &mut Ui, board: &Board, sx: u16, sy: u16) {
+    let width = board.width;
+    let visible = board.height - 10;
+    let filled = |y: usize, x: usize| board.is_filled(x, y + 10);
+
+    ui.print_at(sx, sy, &format!("{:^1$}", "OPPONENT", width + 2));
+    for line in 0..visible / 2 {
+        let cells: String = (0..width)
+            .map(|x| match (filled(line * 2, x), filled(line * 2 + 1, x)) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            })
+            .collect();
+        ui.print_at(sx, sy + 1 + line as u16, &format!("█{}█", cells));
+    }
+    ui.print_at(sx, sy + 1 + (visible / 2) as u16, &"▀".repeat(width + 2));
+    ui.flush();
+}
+
+// Two rows of a 4 cell wide preview box, with the piece centered in it
+fn mini_piece_rows(mino: TetrominoType, tile: &str) -> [String; 2] {
+    let cells = Tetromino::new(mino).cells();
+
+    let min_col = cells.iter().map(|&(col, _)| col).min().unwrap();
+    let max_col = cells.iter().map(|&(col, _)| col).max().unwrap();
+    let min_row = cells.iter().map(|&(_, row)| row).min().unwrap();
+    let pad = 4 - (max_col - min_col + 1);
+
+    let mut rows = [String::new(), String::new()];
+    for (index, line) in rows.iter_mut().enumerate() {
+        line.push_str(&" ".repeat(pad));
+        for col in min_col..min_col + 4 - pad {
+            if cells.contains(&(col, min_row + index)) {
+                line.push_str(tile);
+            } else {
+                line.push_str("  ");
+            }
+        }
+        line.push_str(&" ".repeat(pad));
+    }
+    rows
+}
+
+fn draw_mini_piece(ui: &mut Ui, mino: Option<TetrominoType>, x: u16, y: u16, grayed: bool) {
+    let rows = match mino {
+        Some(mino) => {
+            let tile = if grayed {
+                GRAY_TILE
+            } else {
+                get_tile_color(mino)
+            };
+            mini_piece_rows(mino, tile)
+        }
+        None => [" ".repeat(8), " ".repeat(8)],
+    };
+
+    for (index, row) in rows.iter().enumerate() {
+        ui.print_at(x, y + index as u16, row);
+    }
+}
+
+fn draw_side_panels(ui: &mut Ui, board: &Board, next_queue: usize, sx: u16, sy: u16, bx_px: u16) {
+    let hold_x = sx.saturating_sub(10);
+    ui.print_at(hold_x, sy, "  HOLD  ");
+    draw_mini_piece(ui, board.held_piece(), hold_x, sy + 1, board.hold_used());
+
+    let next_x = sx + bx_px + 2;
+    if next_queue > 0 {
+        ui.print_at(next_x, sy, "  NEXT  ");
+    }
+
+    for (index, mino) in board.upcoming().iter().take(next_queue).enumerate() {
+        draw_mini_piece(ui, Some(*mino), next_x, sy + 1 + index as u16 * 3, false);
+    }
+    ui.flush();
+}
+
+struct Frame<'a> {
+    board: &'a Board,
+    hud: Vec<String>,
+    elapsed: Duration,
+    fps: f64,
+    finesse_hint: Option<&'a [Move]>,
+}
+
+fn stat_lines(entry: StatEntry, frame: &Frame) -> Vec<String> {
+    let board = frame.board;
+    let stats = &board.stats;
+    let pps = per_second(board.pieces_placed, frame.elapsed);
+
+    match entry {
+        StatEntry::Score => vec![format!("SCORE:   {}", stats.score)],
+        StatEntry::Fps => vec![format!("FPS:     {:.2}", frame.fps)],
+        StatEntry::Pps => vec![format!("PPS:     {:.2}", pps)],
+        StatEntry::Lines => vec![format!("LINES:   {}", board.lines_cleared)],
+        StatEntry::Time => vec![format!("TIME:    {}", format_time(frame.elapsed))],
+        StatEntry::Kpp => vec![format!("KPP:     {:.2}", stats.keys_per_piece())],
+        StatEntry::Apm => vec![format!(
+            "APM:     {:.1}",
+            per_minute(stats.attack, frame.elapsed)
+        )],
+        StatEntry::Lpm => vec![format!(
+            "LPM:     {:.1}",
+            per_minute(board.lines_cleared, frame.elapsed)
+        )],
+        StatEntry::Finesse => {
+            let hint = frame
+                .finesse_hint
+                .map(|moves| format!("  {}", sequence_string(moves)))
+                .unwrap_or_default();
+
+            vec![
+                format!(
+                    "FIN:     {} ({:.1}%)",
+                    board.finesse.faults,
+                    board.finesse.accuracy()
+                ),
+                hint,
+            ]
+        }
+        StatEntry::Pieces => {
+            let count = |mino| format!("{:?} {:<3}", mino, stats.pieces_of(mino));
+            vec![
+                [
+                    TetrominoType::I,
+                    TetrominoType::O,
+                    TetrominoType::T,
+                    TetrominoType::S,
+                ]
+                .map(count)
+                .join(""),
+                [TetrominoType::Z, TetrominoType::J, TetrominoType::L]
+                    .map(count)
+                    .join(""),
+            ]
+        }
+        StatEntry::Holds => vec![format!("HOLDS:   {}", stats.holds)],
+        StatEntry::TetrisRate => vec![format!(
+            "TETRIS:  {:.1}%",
+            stats.tetris_rate(board.lines_cleared)
+        )],
+        StatEntry::TSpinRate => vec![format!("TSPIN:   {:.1}%", stats.tspin_rate())],
+    }
+}
+
+fn draw_stats(ui: &mut Ui, settings: &Settings, frame: &Frame, x: u16, y: u16) {
+    let mut lines = frame.hud.clone();
+    lines.extend(
+        StatEntry::ALL
+            .iter()
+            .filter(|entry| settings.shows(**entry))
+            .flat_map(|entry| stat_lines(*entry, frame)),
+    );
+
+    for (index, line) in lines.iter().enumerate() {
+        execute!(ui.stdout, cursor::MoveTo(x, y + index as u16)).unwrap();
+        print!("{:<22}", line);
+    }
+}
+
+// A line of text across the middle of the well
+fn draw_banner(ui: &mut Ui, sx: u16, sy: u16, text: &str) {
+    ui.print_at(sx + 1, sy + 9, "                    ");
+    ui.print_at(sx + 1, sy + 10, &format!("{:^20}", text));
+    ui.print_at(sx + 1, sy + 11, "                    ");
+    ui.flush();
+}
+
+// Holds the final position on screen for a moment before the results,
+// swallowing keys so a held drop does not skip straight past them.
+fn freeze(ui: &mut Ui, sx: u16, sy: u16, text: &str) -> Result<(), Quit> {
+    draw_banner(ui, sx, sy, text);
+
+    let until = Instant::now() + FREEZE_TIME;
+    while Instant::now() < until {
+        while ui.try_key()?.is_some() {}
+        thread::sleep(Duration::from_millis(10));
+    }
+    Ok(())
+}
+
+fn show_results(
+    ui: &mut Ui,
+    settings: &Settings,
+    title: &str,
+    lines: &[String],
+) -> Result<(), Quit> {
+    ui.clear();
+
+    loop {
+        let key = ui.wait_key_redraw(Duration::from_millis(250), |ui| {
+            let (_, rows) = crossterm::terminal::size().unwrap();
+            let top = rows.saturating_sub(lines.len() as u16 + 4) / 2;
+
+            ui.print_centered_styled(top, title, Attribute::Bold);
+            for (index, line) in lines.iter().enumerate() {
+                ui.print_centered(top + 2 + index as u16, line);
+            }
+            ui.flush();
+        })?;
+
+        match settings.keys.action(key.code) {
+            Some(InputEvent::HardDrop) | Some(InputEvent::Quit) => return Ok(()),
+            _ if key.code == crossterm::event::KeyCode::Enter => return Ok(()),
+            _ => (),
+        }
+    }
+}
+
+pub fn run(
+    ui: &mut Ui,
+    settings: &Settings,
+    mode: GameMode,
+    start: Option<&Position>,
+) -> Result<(), Quit> {
+    match mode::controller(mode, settings) {
+        Some(controller) => run_with(ui, settings, mode, controller, start),
+        // Nothing to play from here, so straight back to the menu
+        None => Ok(()),
+    }
+}
+
+// Plays a game driven by a controller the caller built, for modes like
+// puzzles that need more than the settings to set up.
+pub fn run_with(
+    ui: &mut Ui,
+    settings: &Settings,
+    mode: GameMode,
+    mut controller: Box<dyn ModeController>,
+    start: Option<&Position>,
+) -> Result<(), Quit> {
+    let keys = &settings.keys;
+
+    let seed = controller.seed().unwrap_or_else(rand::random);
+    let mut board = mode::start_board(controller.as_mut(), seed, start);
+
+    let mut frame_count = 0;
+    let mut clock = Clock::default();
+    ui.clear();
+
+    let mut last_drop_time = Instant::now();
+    let mut last_move_time = Instant::now();
+    let mut last_rotate_time = Instant::now();
+
+    let mut finesse_faults = 0;
+    let mut finesse_hint_until = Instant::now();
+
+    let mut notice: Option<(String, Instant)> = None;
+    let mut hints = Hints::from_settings(settings);
+
+    loop {
+        let suspended = ui.take_suspended();
+        if !clock.paused() {
+            clock.skip(suspended);
+        }
+
+        let (cols, rows) = crossterm::terminal::size().unwrap();
+
+        let bx_px = (board.width * 2) + 2;
+        let by_px = (board.height + 1 - 10) + 1;
+
+        let sx = (cols - bx_px as u16) / 2;
+        let sy = (rows - by_px as u16) / 2;
+
+        if clock.paused() {
+            draw_banner(ui, sx, sy, "PAUSED");
+
+            while let Some(key_event) = ui.try_key()? {
+                if key_event.kind != KeyEventKind::Press {
+                    continue;
+                }
+
+                match keys.action(key_event.code) {
+                    Some(InputEvent::Quit) => return Ok(()),
+                    Some(InputEvent::Pause) => clock.resume(),
+                    _ => (),
+                }
+            }
+
+            thread::sleep(Duration::from_millis(1000 / 120));
+            continue;
+        }
+
+        // Drawing loop
+        frame_count += 1;
+        let elapsed = clock.elapsed();
+
+        if !controller.owns_clock() {
+            board.update(elapsed);
+        }
+        hints.update(&mut board);
+
+        draw_board(ui, &board, sx, sy);
+
+        if board.finesse.faults != finesse_faults {
+            finesse_faults = board.finesse.faults;
+            finesse_hint_until = Instant::now() + FINESSE_HINT_TIME;
+        }
+
+        let finesse_hint = match &board.finesse.last_fault {
+            Some(optimal) if settings.finesse_hints && Instant::now() < finesse_hint_until => {
+                Some(optimal.as_slice())
+            }
+            _ => None,
+        };
+
+        let mut hud = controller.hud(&board, elapsed);
+        if let Some((text, until)) = &notice {
+            if Instant::now() < *until {
+                hud.push(text.clone());
+            }
+        }
+        hud.extend(hints.hud());
+
+        let frame = Frame {
+            board: &board,
+            hud,
+            elapsed,
+            fps: frame_count as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+            finesse_hint,
+        };
+        draw_stats(ui, settings, &frame, sx.saturating_sub(24), sy + 4);
+
+        draw_side_panels(ui, &board, settings.next_queue, sx, sy, bx_px as u16);
+
+        // Past the next queue, if the terminal is wide enough
+        let ox = sx + bx_px as u16 + 12;
+        if let Some(opponent) = controller.opponent() {
+            if ox + bx_px as u16 <= cols {
+                draw_board(ui, opponent, ox, sy);
+            }
+        } else if let Some(opponent) = controller.small_opponent() {
+            if ox + opponent.width as u16 + 2 <= cols {
+                draw_small_board(ui, opponent, ox, sy);
+            }
+        }
+
+        while let Some(key_event) = ui.try_key()? {
+            if key_event.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            let action = keys.action(key_event.code);
+            if controller.plays_itself()
+                && !matches!(
+                    action,
+                    Some(InputEvent::Quit | InputEvent::Pause | InputEvent::CopyPosition)
+                )
+            {
+                continue;
+            }
+
+            if let Some(input) = action {
+                if controller.take_input(input) {
+                    continue;
+                }
+            }
+
+            match action {
+                Some(InputEvent::Quit) => return Ok(()),
+                // A mode that keeps time with someone else cannot stop it
+                Some(InputEvent::Pause) if !controller.owns_clock() => clock.pause(),
+                Some(InputEvent::HardDrop) if last_drop_time.elapsed() >= DEBOUNCE => {
+                    board.hard_drop();
+                    last_drop_time = Instant::now();
+                }
+                Some(InputEvent::MoveRight) if last_move_time.elapsed() >= DEBOUNCE => {
+                    board.move_tetromino((1, 0));
+                    last_move_time = Instant::now();
+                }
+                Some(InputEvent::MoveLeft) if last_move_time.elapsed() >= DEBOUNCE => {
+                    board.move_tetromino((-1, 0));
+                    last_move_time = Instant::now();
+                }
+                Some(InputEvent::RotateCW) if last_rotate_time.elapsed() >= DEBOUNCE => {
+                    board.rotate(RotationDirection::Clockwise);
+                    last_rotate_time = Instant::now();
+                }
+                Some(InputEvent::RotateCCW) if last_rotate_time.elapsed() >= DEBOUNCE => {
+                    board.rotate(RotationDirection::CounterClockwise);
+                    last_rotate_time = Instant::now();
+                }
+                Some(InputEvent::SoftDrop) => board.soft_harddrop(),
+                Some(InputEvent::Hold) => board.hold_piece(),
+                Some(InputEvent::Undo) => controller.undo(&mut board),
+                Some(InputEvent::Redo) => controller.redo(&mut board),
+                Some(InputEvent::Retry) => controller.retry(&mut board),
+                Some(InputEvent::Hint) => hints.cycle(),
+                Some(InputEvent::CopyPosition) => {
+                    let text = match position::export(&board.position()) {
+                        Ok(fumen) => {
+                            ui.copy_to_clipboard(&fumen);
+                            "COPIED".to_string()
+                        }
+                        Err(err) => format!("NOT SAVED: {}", err),
+                    };
+                    notice = Some((text, Instant::now() + NOTICE_TIME));
+                }
+                _ => (),
+            }
+        }
+
+        let elapsed = clock.elapsed();
+        if let Some(end) = controller.update(&mut board, elapsed) {
+            draw_board(ui, &board, sx, sy);
+            freeze(ui, sx, sy, end.title())?;
+
+            let elapsed = controller
+                .time_limit()
+                .map_or(elapsed, |limit| elapsed.min(limit));
+
+            let pps = per_second(board.pieces_placed, elapsed);
+            let mut results = vec![
+                format!("Mode     {:>10}", mode.name()),
+                format!("Score    {:>10}", board.stats.score),
+                format!("Time     {:>10}", format_time(elapsed)),
+                format!("Lines    {:>10}", board.lines_cleared),
+                format!("Pieces   {:>10}", board.pieces_placed),
+                format!("PPS      {:>10.2}", pps),
+                format!("APM      {:>10.1}", per_minute(board.stats.attack, elapsed)),
+                format!("KPP      {:>10.2}", board.stats.keys_per_piece()),
+                format!("Finesse  {:>9.1}%", board.finesse.accuracy()),
+            ];
+            results.extend(controller.results(&board, elapsed));
+
+            let record = GameRecord {
+                mode,
+                settings_hash: settings.gameplay_hash(mode),
+                seed: board.seed(),
+                score: board.stats.score,
+                time_ms: elapsed.as_millis() as u64,
+                lines: board.lines_cleared,
+                pieces: board.pieces_placed,
+                pps,
+                attack: board.stats.attack,
+                keys: board.stats.keys,
+                finesse_graded: board.finesse.graded,
+                finesse_faults: board.finesse.faults,
+                date: now_unix(),
+                finished: end.completed(),
+            };
+
+            // A game played with hints is no match for one played without
+            if mode.recorded() && hints.used() {
+                results.extend([String::new(), "Hints used, not saved".to_string()]);
+            } else if mode.recorded() {
+                match records::record_game(record) {
+                    Ok(true) => results.extend([String::new(), "New personal best!".to_string()]),
+                    Ok(false) => (),
+                    Err(err) => results.extend([String::new(), format!("Not saved: {}", err)]),
+                }
+            }
+
+            return show_results(ui, settings, end.title(), &results);
+        }
+
+        thread::sleep(Duration::from_millis(1000 / 120));
+    }
+}
@@ -0,0 +1,99 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// Picks the hole column for each new garbage row.
+pub struct HoleGenerator {
+    // Whether a hole may land under the one before it. When it may not,
+    // every row's hole moves and messiness has no say.
+    repeat_holes: bool,
+    // Percent chance each row's hole is placed at random rather than under
+    // the previous row's hole
+    messiness: u8,
+    last_hole: Option<usize>,
+    rng: StdRng,
+}
+
+impl HoleGenerator {
+    pub fn new(seed: u64, repeat_holes: bool, messiness: u8) -> Self {
+        HoleGenerator {
+            repeat_holes,
+            messiness: messiness.min(100),
+            last_hole: None,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn next_hole(&mut self, width: usize) -> usize {
+        let hole = match self.last_hole {
+            Some(last) if !self.repeat_holes => {
+                let hole = self.rng.gen_range(0..width - 1);
+                if hole >= last {
+                    hole + 1
+                } else {
+                    hole
+                }
+            }
+            Some(last) if self.rng.gen_range(0..100) >= self.messiness => last,
+            _ => self.rng.gen_range(0..width),
+        };
+
+        self.last_hole = Some(hole);
+        hole
+    }
+
+    pub fn holes(&mut self, width: usize, rows: usize) -> Vec<usize> {
+        (0..rows).map(|_| self.next_hole(width)).collect()
+    }
+}
+
+// Garbage sent to a player that has yet to rise. Lines they send back first
+// cancel what is waiting, and the rest rises once they place a piece without
+// clearing anything.
+#[derive(Default)]
+pub struct GarbageQueue {
+    pending: usize,
+}
+
+impl GarbageQueue {
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    pub fn receive(&mut self, lines: usize) {
+        self.pending += lines;
+    }
+
+    // Cancels waiting garbage with `attack`, returning what is left over to
+    // send to the opponent
+    pub fn counter(&mut self, attack: usize) -> usize {
+        let cancelled = attack.min(self.pending);
+        self.pending -= cancelled;
+        attack - cancelled
+    }
+
+    pub fn take(&mut self) -> usize {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tidy_holes_repeat() {
+        let mut holes = HoleGenerator::new(1, true, 0);
+        let holes = holes.holes(10, 20);
+        assert!(holes.iter().all(|hole| *hole == holes[0]));
+    }
+
+    #[test]
+    fn holes_never_repeat_when_turned_off() {
+        for messiness in [0, 50, 100] {
+            let mut holes = HoleGenerator::new(1, false, messiness);
+            let holes = holes.holes(10, 100);
+            assert!(holes.windows(2).all(|pair| pair[0] != pair[1]));
+            assert!(holes.iter().all(|hole| *hole < 10));
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use crate::board::Board;
+use crate::bot::{self, Plan, Weights};
+use crate::position::Position;
+use crate::settings::Settings;
+use crate::tbp::Tbp;
+
+// Suggestions the hint key steps through before it hides them again
+pub const HINTS: usize = 3;
+
+// Where the suggestions come from
+enum Advisor {
+    // The bot's own search, run in the background like when it plays
+    Heuristic(Option<Receiver<Vec<Plan>>>),
+    External(Tbp),
+}
+
+// Suggested placements for the player's piece, outlined on the field. The
+// hint key shows the best one, then the next best and so on, and hides them
+// after the last. They are worked out again for every new piece while shown.
+pub struct Hints {
+    external_bot: Option<PathBuf>,
+    // Started the first time a hint is asked for
+    advisor: Option<Advisor>,
+    // Which suggestion is on the field, if any
+    shown: Option<usize>,
+    // None until the advisor answers for the current piece
+    plans: Option<Vec<Plan>>,
+    // The pieces placed and position the suggestions are for
+    made_for: Option<(usize, Position)>,
+    used: bool,
+}
+
+impl Hints {
+    // Asks the external bot given on the command line if there is one,
+    // otherwise the built in one
+    pub fn from_settings(settings: &Settings) -> Self {
+        Hints {
+            external_bot: settings.external_bot.clone(),
+            advisor: None,
+            shown: None,
+            plans: None,
+            made_for: None,
+            used: false,
+        }
+    }
+
+    // Whether a hint was shown at all this game
+    pub fn used(&self) -> bool {
+        self.used
+    }
+
+    // Shows the next suggestion, or hides them after the last one
+    pub fn cycle(&mut self) {
+        if self.advisor.is_none() {
+            self.advisor = Some(match &self.external_bot {
+                Some(path) => Advisor::External(Tbp::spawn(path)),
+                None => Advisor::Heuristic(None),
+            });
+        }
+
+        self.used = true;
+        self.shown = match self.shown {
+            None => Some(0),
+            Some(index) if index + 1 < HINTS => Some(index + 1),
+            Some(_) => None,
+        };
+    }
+
+    // Suggestions for `board` once the advisor has them
+    fn advise(&mut self, board: &Board) -> Option<Vec<Plan>> {
+        let thinking = match self.advisor.as_mut()? {
+            Advisor::Heuristic(thinking) => thinking,
+            Advisor::External(tbp) => return tbp.advise(board),
+        };
+
+        let Some(receiver) = thinking else {
+            let (tx, rx) = mpsc::channel();
+            let board = board.clone();
+            thread::spawn(move || {
+                tx.send(bot::ranked(&board, &Weights::default(), HINTS))
+                    .ok()
+            });
+
+            *thinking = Some(rx);
+            return None;
+        };
+
+        match receiver.try_recv() {
+            Ok(plans) => {
+                *thinking = None;
+                Some(plans)
+            }
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                *thinking = None;
+                Some(Vec::new())
+            }
+        }
+    }
+
+    // Called every frame. Keeps the suggestions in step with the board and
+    // outlines the one being shown.
+    pub fn update(&mut self, board: &mut Board) {
+        let Some(index) = self.shown else {
+            board.clear_hint();
+            return;
+        };
+
+        let now = (board.pieces_placed, board.position());
+        if self.made_for.as_ref() != Some(&now) {
+            self.plans = None;
+            if let Some(Advisor::Heuristic(thinking)) = &mut self.advisor {
+                *thinking = None;
+            }
+            self.made_for = Some(now);
+        }
+
+        if self.plans.is_none() && board.active().is_some() {
+            self.plans = self.advise(board);
+        }
+
+        match self.plans.as_ref().and_then(|plans| plans.get(index)) {
+            Some(plan) => {
+                let cells: Vec<(usize, usize)> = plan
+                    .placement
+                    .cells()
+                    .into_iter()
+                    .map(|(x, y)| (x as usize, y as usize))
+                    .collect();
+                board.set_hint(plan.placement.mino.tr_type, &cells);
+            }
+            None => board.clear_hint(),
+        }
+    }
+
+    // What the HUD says about the suggestion being shown
+    pub fn hud(&self) -> Option<String> {
+        let index = self.shown?;
+        let line = match self.plans.as_ref().map(|plans| plans.get(index)) {
+            Some(Some(plan)) if plan.hold => format!("HINT:    {}/{} HOLD", index + 1, HINTS),
+            Some(Some(_)) => format!("HINT:    {}/{}", index + 1, HINTS),
+            Some(None) => format!("HINT:    {}/{} NONE", index + 1, HINTS),
+            None => "HINT:    ...".to_string(),
+        };
+        Some(line)
+    }
+}
@@ -1,10 +1,4 @@
-use std::time::{Duration, Instant};
-
-pub enum Direction {
-    Left,
-    Right,
-}
-
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum InputEvent {
     MoveLeft,
     MoveRight,
@@ -14,35 +8,11 @@ pub enum InputEvent {
     SoftDrop,
     HardDrop,
     Hold,
+    Pause,
     Quit,
-}
-
-pub struct Input {
-    das: Duration,
-    das_timer: Option<Instant>,
-    arr: Duration,
-    autoshift: bool,
-    direction: Option<Direction>,
-}
-
-impl Input {
-    pub fn new(das: Duration, arr: Duration) -> Self {
-        Self { 
-            das, 
-            das_timer: None,
-            arr, 
-            autoshift: false,
-            direction: None,
-        }
-    }
-
-    pub fn start_das_timing(&mut self, direction: Direction) {
-        self.das_timer = Some(Instant::now());
-        self.direction = Some(direction);
-    }
-
-    pub fn reset_das(&mut self) {
-        self.das_timer = None;
-        self.direction = None;
-    }
+    Undo,
+    Redo,
+    Retry,
+    CopyPosition,
+    Hint,
 }
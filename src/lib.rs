@@ -0,0 +1,33 @@
+pub mod board;
+pub mod bot;
+pub mod cli;
+pub mod constants;
+pub mod dashboard;
+pub mod demo;
+pub mod dig;
+pub mod finesse;
+pub mod fumen;
+pub mod game;
+pub mod garbage;
+pub mod hint;
+pub mod input;
+pub mod menu;
+pub mod mino;
+pub mod mode;
+pub mod movegen;
+pub mod net;
+pub mod online;
+pub mod pc;
+pub mod position;
+pub mod puzzle;
+pub mod records;
+pub mod settings;
+pub mod sim;
+pub mod stats;
+pub mod survival;
+pub mod tbp;
+pub mod terminal;
+pub mod trainer;
+pub mod ui;
+pub mod util;
+pub mod versus;
@@ -0,0 +1,569 @@
+use std::time::Duration;
+
+use crossterm::event::KeyCode;
+use crossterm::style::Attribute;
+
+use crate::bot::BOT_PPS;
+use crate::constants::MAX_NEXT_QUEUE;
+use crate::dashboard::Dashboard;
+use crate::dig::{DIG_MESSINESS, DIG_ROWS};
+use crate::input::InputEvent;
+use crate::mode::{GameMode, MARATHON_LEVELS, ULTRA_MINUTES};
+use crate::puzzle::{self, Puzzle};
+use crate::records::{GameRecord, Records};
+use crate::settings::{key_name, Keybinds, Settings};
+use crate::stats::StatEntry;
+use crate::trainer::Opener;
+use crate::ui::{Quit, Ui};
+use crate::util::{format_date, format_time};
+
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+
+pub enum MenuChoice {
+    Play(GameMode),
+    Settings,
+    Statistics,
+    Quit,
+}
+
+enum Nav {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+    Back,
+}
+
+fn nav(keys: &Keybinds, code: KeyCode) -> Option<Nav> {
+    match keys.action(code) {
+        Some(InputEvent::RotateCW) => Some(Nav::Up),
+        Some(InputEvent::SoftDrop) => Some(Nav::Down),
+        Some(InputEvent::MoveLeft) => Some(Nav::Left),
+        Some(InputEvent::MoveRight) => Some(Nav::Right),
+        Some(InputEvent::HardDrop) => Some(Nav::Select),
+        Some(InputEvent::Quit) => Some(Nav::Back),
+        _ => match code {
+            KeyCode::Enter => Some(Nav::Select),
+            _ => None,
+        },
+    }
+}
+
+fn menu_top(items: usize) -> u16 {
+    let (_, rows) = crossterm::terminal::size().unwrap();
+    rows.saturating_sub(items as u16 + 4) / 2
+}
+
+fn draw_title(ui: &mut Ui, y: u16, title: &str) {
+    ui.print_centered_styled(y, title, Attribute::Bold);
+}
+
+fn draw_items(ui: &mut Ui, y: u16, items: &[(String, bool)], selected: usize) {
+    for (index, (label, enabled)) in items.iter().enumerate() {
+        let text = if index == selected {
+            format!("> {} <", label)
+        } else {
+            format!("  {}  ", label)
+        };
+
+        let attribute = match (index == selected, enabled) {
+            (true, _) => Attribute::Reverse,
+            (false, true) => Attribute::Reset,
+            (false, false) => Attribute::Dim,
+        };
+
+        ui.print_centered_styled(y + index as u16, &text, attribute);
+    }
+}
+
+#[derive(Default)]
+pub struct Menu {
+    selected: usize,
+}
+
+impl Menu {
+    fn items() -> Vec<(String, bool)> {
+        let mut items: Vec<(String, bool)> = GameMode::ALL
+            .iter()
+            .map(|mode| match mode.available() {
+                true => (mode.name().to_string(), true),
+                false => (format!("{} (soon)", mode.name()), false),
+            })
+            .collect();
+
+        items.push(("Settings".to_string(), true));
+        items.push(("Statistics".to_string(), true));
+        items.push(("Quit".to_string(), true));
+        items
+    }
+
+    fn choice(&self) -> MenuChoice {
+        let modes = GameMode::ALL.len();
+
+        match self.selected {
+            index if index < modes => MenuChoice::Play(GameMode::ALL[index]),
+            index if index == modes => MenuChoice::Settings,
+            index if index == modes + 1 => MenuChoice::Statistics,
+            _ => MenuChoice::Quit,
+        }
+    }
+
+    pub fn run(&mut self, ui: &mut Ui, settings: &Settings) -> Result<MenuChoice, Quit> {
+        let items = Self::items();
+        ui.clear();
+
+        loop {
+            let selected = self.selected;
+            let key = ui.wait_key_redraw(REDRAW_INTERVAL, |ui| {
+                let top = menu_top(items.len());
+                draw_title(ui, top, "T E T . R S");
+                draw_items(ui, top + 2, &items, selected);
+                ui.flush();
+            })?;
+
+            match nav(&settings.keys, key.code) {
+                Some(Nav::Up) => {
+                    self.selected = (self.selected + items.len() - 1) % items.len();
+                }
+                Some(Nav::Down) => {
+                    self.selected = (self.selected + 1) % items.len();
+                }
+                Some(Nav::Select) if items[self.selected].1 => return Ok(self.choice()),
+                Some(Nav::Back) => return Ok(MenuChoice::Quit),
+                _ => (),
+            }
+        }
+    }
+}
+
+const BINDABLE: [(InputEvent, &str); 14] = [
+    (InputEvent::MoveLeft, "Move left"),
+    (InputEvent::MoveRight, "Move right"),
+    (InputEvent::RotateCW, "Rotate CW"),
+    (InputEvent::RotateCCW, "Rotate CCW"),
+    (InputEvent::SoftDrop, "Soft drop"),
+    (InputEvent::HardDrop, "Hard drop"),
+    (InputEvent::Hold, "Hold"),
+    (InputEvent::Pause, "Pause"),
+    (InputEvent::Quit, "Back / Quit"),
+    (InputEvent::Undo, "Undo"),
+    (InputEvent::Redo, "Redo"),
+    (InputEvent::Retry, "Retry"),
+    (InputEvent::CopyPosition, "Copy position"),
+    (InputEvent::Hint, "Hint"),
+];
+
+#[derive(Copy, Clone)]
+enum SettingRow {
+    Bind(InputEvent, &'static str),
+    NextQueue,
+    FinesseHints,
+    StartLevel,
+    Endless,
+    UltraLength,
+    DigRows,
+    DigRepeatHoles,
+    DigMessiness,
+    PcOpener,
+    AutoRestart,
+    BotSpeed,
+    Stat(StatEntry),
+    Reset,
+    Back,
+}
+
+fn setting_rows() -> Vec<SettingRow> {
+    let mut rows: Vec<SettingRow> = BINDABLE
+        .iter()
+        .map(|(event, label)| SettingRow::Bind(*event, label))
+        .collect();
+
+    rows.extend([
+        SettingRow::NextQueue,
+        SettingRow::FinesseHints,
+        SettingRow::StartLevel,
+        SettingRow::Endless,
+        SettingRow::UltraLength,
+        SettingRow::DigRows,
+        SettingRow::DigRepeatHoles,
+        SettingRow::DigMessiness,
+        SettingRow::PcOpener,
+        SettingRow::AutoRestart,
+        SettingRow::BotSpeed,
+    ]);
+    rows.extend(StatEntry::ALL.iter().map(|entry| SettingRow::Stat(*entry)));
+    rows.extend([SettingRow::Reset, SettingRow::Back]);
+    rows
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "On"
+    } else {
+        "Off"
+    }
+}
+
+// The option after `current`, wrapping around
+fn cycle<T: Copy + PartialEq>(options: &[T], current: T) -> T {
+    let index = options
+        .iter()
+        .position(|option| *option == current)
+        .unwrap_or(0);
+    options[(index + 1) % options.len()]
+}
+
+fn setting_label(row: SettingRow, settings: &Settings) -> String {
+    match row {
+        SettingRow::Bind(event, label) => {
+            format!("{:<14}{:>8}", label, key_name(settings.keys.get(&event)))
+        }
+        SettingRow::NextQueue => format!("{:<14}{:>8}", "Next queue", settings.next_queue),
+        SettingRow::FinesseHints => {
+            format!(
+                "{:<14}{:>8}",
+                "Finesse hints",
+                on_off(settings.finesse_hints)
+            )
+        }
+        SettingRow::StartLevel => {
+            format!("{:<14}{:>8}", "Start level", settings.marathon_start_level)
+        }
+        SettingRow::Endless => {
+            format!("{:<14}{:>8}", "Endless", on_off(settings.marathon_endless))
+        }
+        SettingRow::UltraLength => {
+            let minutes = format!("{} min", settings.ultra_minutes);
+            format!("{:<14}{:>8}", "Ultra length", minutes)
+        }
+        SettingRow::DigRows => format!("{:<14}{:>8}", "Dig rows", settings.dig_rows),
+        SettingRow::DigRepeatHoles => {
+            format!(
+                "{:<14}{:>8}",
+                "Repeat holes",
+                on_off(settings.dig_repeat_holes)
+            )
+        }
+        SettingRow::DigMessiness => {
+            let messiness = format!("{}%", settings.dig_messiness);
+            format!("{:<14}{:>8}", "Messiness", messiness)
+        }
+        SettingRow::PcOpener => {
+            format!("{:<14}{:>8}", "PC opener", settings.pc_opener.name())
+        }
+        SettingRow::AutoRestart => {
+            format!(
+                "{:<14}{:>8}",
+                "Auto restart",
+                on_off(settings.pc_auto_restart)
+            )
+        }
+        SettingRow::BotSpeed => format!("{:<14}{:>8}", "Bot PPS", settings.bot_pps),
+        SettingRow::Stat(entry) => {
+            format!("{:<14}{:>8}", entry.name(), on_off(settings.shows(entry)))
+        }
+        SettingRow::Reset => "Reset to defaults".to_string(),
+        SettingRow::Back => "Back".to_string(),
+    }
+}
+
+// Most settings shown at once; the list scrolls to keep the selection in view
+const SETTING_ROWS: usize = 16;
+
+pub fn settings_menu(ui: &mut Ui, settings: &mut Settings) -> Result<(), Quit> {
+    let rows = setting_rows();
+    let mut selected: usize = 0;
+    ui.clear();
+
+    loop {
+        let items: Vec<(String, bool)> = rows
+            .iter()
+            .map(|row| (setting_label(*row, settings), true))
+            .collect();
+        let first = (selected + 1).saturating_sub(SETTING_ROWS);
+        let shown = &items[first..items.len().min(first + SETTING_ROWS)];
+
+        let key = ui.wait_key_redraw(REDRAW_INTERVAL, |ui| {
+            let top = menu_top(shown.len() + 2);
+            draw_title(ui, top, "SETTINGS");
+            draw_items(ui, top + 2, shown, selected - first);
+            ui.flush();
+        })?;
+
+        let nav = nav(&settings.keys, key.code);
+
+        match (nav, rows[selected]) {
+            (Some(Nav::Up), _) => {
+                selected = (selected + rows.len() - 1) % rows.len();
+                ui.clear();
+            }
+            (Some(Nav::Down), _) => {
+                selected = (selected + 1) % rows.len();
+                ui.clear();
+            }
+            (Some(Nav::Back), _) | (Some(Nav::Select), SettingRow::Back) => break,
+            (Some(Nav::Select), SettingRow::Bind(event, _)) => {
+                let top = menu_top(shown.len() + 2);
+                ui.print_centered(
+                    top + shown.len() as u16 + 3,
+                    "Press a key to bind, Esc to cancel",
+                );
+                ui.flush();
+
+                let key = ui.wait_key()?;
+                if key.code != KeyCode::Esc {
+                    settings.keys.bind(&event, key.code);
+                }
+                ui.clear();
+            }
+            (Some(Nav::Left), SettingRow::NextQueue) => {
+                settings.next_queue = settings.next_queue.saturating_sub(1);
+            }
+            (Some(Nav::Right), SettingRow::NextQueue) => {
+                settings.next_queue = (settings.next_queue + 1).min(MAX_NEXT_QUEUE);
+            }
+            (Some(Nav::Select), SettingRow::NextQueue) => {
+                settings.next_queue = (settings.next_queue + 1) % (MAX_NEXT_QUEUE + 1);
+            }
+            (Some(Nav::Left), SettingRow::StartLevel) => {
+                settings.marathon_start_level = (settings.marathon_start_level - 1).max(1);
+            }
+            (Some(Nav::Right), SettingRow::StartLevel) => {
+                settings.marathon_start_level =
+                    (settings.marathon_start_level + 1).min(MARATHON_LEVELS);
+            }
+            (Some(Nav::Select), SettingRow::StartLevel) => {
+                settings.marathon_start_level = settings.marathon_start_level % MARATHON_LEVELS + 1;
+            }
+            (Some(Nav::Left | Nav::Right | Nav::Select), SettingRow::UltraLength) => {
+                settings.ultra_minutes = cycle(&ULTRA_MINUTES, settings.ultra_minutes);
+            }
+            (Some(Nav::Left | Nav::Right | Nav::Select), SettingRow::DigRows) => {
+                settings.dig_rows = cycle(&DIG_ROWS, settings.dig_rows);
+            }
+            (Some(Nav::Left | Nav::Right | Nav::Select), SettingRow::DigRepeatHoles) => {
+                settings.dig_repeat_holes = !settings.dig_repeat_holes;
+            }
+            (Some(Nav::Left | Nav::Right | Nav::Select), SettingRow::DigMessiness) => {
+                settings.dig_messiness = cycle(&DIG_MESSINESS, settings.dig_messiness);
+            }
+            (Some(Nav::Left | Nav::Right | Nav::Select), SettingRow::PcOpener) => {
+                settings.pc_opener = cycle(&Opener::ALL, settings.pc_opener);
+            }
+            (Some(Nav::Left | Nav::Right | Nav::Select), SettingRow::AutoRestart) => {
+                settings.pc_auto_restart = !settings.pc_auto_restart;
+            }
+            (Some(Nav::Left | Nav::Right | Nav::Select), SettingRow::BotSpeed) => {
+                settings.bot_pps = cycle(&BOT_PPS, settings.bot_pps);
+            }
+            (Some(Nav::Left | Nav::Right | Nav::Select), SettingRow::Endless) => {
+                settings.marathon_endless = !settings.marathon_endless;
+            }
+            (Some(Nav::Left | Nav::Right | Nav::Select), SettingRow::FinesseHints) => {
+                settings.finesse_hints = !settings.finesse_hints;
+            }
+            (Some(Nav::Left | Nav::Right | Nav::Select), SettingRow::Stat(entry)) => {
+                settings.toggle_stat(entry);
+            }
+            (Some(Nav::Select), SettingRow::Reset) => {
+                *settings = Settings {
+                    external_bot: settings.external_bot.take(),
+                    ..Settings::default()
+                };
+                ui.clear();
+            }
+            _ => (),
+        }
+    }
+
+    // Keep playing with the new settings even if they could not be written out
+    let _ = settings.save();
+    Ok(())
+}
+
+const LEADERBOARD_SIZE: usize = 10;
+const PB_HISTORY_SIZE: usize = 8;
+
+fn record_row(rank: usize, game: &GameRecord) -> String {
+    format!(
+        "{:>2}  {:>9}  {:>8}  {:>5}  {:>5.2}  {}",
+        rank,
+        format_time(game.time()),
+        game.score,
+        game.lines,
+        game.pps,
+        format_date(game.date)
+    )
+}
+
+// Leaderboard and personal best history for one mode, counting only games
+// played with the current settings for it
+fn statistics_lines(records: &Records, mode: GameMode, settings_hash: u64) -> Vec<String> {
+    let header = format!(
+        "{:>2}  {:>9}  {:>8}  {:>5}  {:>5}  {:<10}",
+        "#", "Time", "Score", "Lines", "PPS", "Date"
+    );
+
+    let leaderboard = records.leaderboard(mode, settings_hash, LEADERBOARD_SIZE);
+    if leaderboard.is_empty() {
+        return vec!["No games recorded with these settings yet.".to_string()];
+    }
+
+    let mut lines = vec!["LEADERBOARD (current settings)".to_string(), header.clone()];
+    lines.extend(
+        leaderboard
+            .iter()
+            .enumerate()
+            .map(|(index, game)| record_row(index + 1, game)),
+    );
+
+    let history = records.pb_history(mode, settings_hash);
+    let skipped = history.len().saturating_sub(PB_HISTORY_SIZE);
+
+    lines.extend([String::new(), "PERSONAL BESTS".to_string(), header]);
+    lines.extend(
+        history
+            .iter()
+            .enumerate()
+            .skip(skipped)
+            .rev()
+            .map(|(index, game)| record_row(index + 1, game)),
+    );
+    lines
+}
+
+// The statistics screen pages through an overview of every game and then
+// each mode's records, with left and right switching between them.
+#[derive(Copy, Clone)]
+enum StatisticsPage {
+    Overview,
+    Mode(GameMode),
+}
+
+impl StatisticsPage {
+    fn name(&self) -> &'static str {
+        match self {
+            StatisticsPage::Overview => "Overview",
+            StatisticsPage::Mode(mode) => mode.name(),
+        }
+    }
+
+    fn lines(&self, records: &Records, settings: &Settings) -> Vec<String> {
+        match self {
+            StatisticsPage::Overview => Dashboard::new(records).lines(),
+            StatisticsPage::Mode(mode) => {
+                statistics_lines(records, *mode, settings.gameplay_hash(*mode))
+            }
+        }
+    }
+}
+
+pub fn statistics_menu(ui: &mut Ui, settings: &Settings) -> Result<(), Quit> {
+    let mut pages = vec![StatisticsPage::Overview];
+    pages.extend(
+        GameMode::ALL
+            .iter()
+            .filter(|mode| mode.available() && mode.recorded())
+            .map(|mode| StatisticsPage::Mode(*mode)),
+    );
+    let mut selected = 0;
+
+    let records = Records::load();
+    ui.clear();
+
+    loop {
+        let page = pages[selected];
+        let lines = match &records {
+            Ok(records) => page.lines(records, settings),
+            Err(err) => vec![format!("Could not read records: {}", err)],
+        };
+
+        let key = ui.wait_key_redraw(REDRAW_INTERVAL, |ui| {
+            let top = menu_top(lines.len() + 2);
+            draw_title(ui, top, "STATISTICS");
+            ui.print_centered(top + 2, &format!("<  {:^10}  >", page.name()));
+            for (index, line) in lines.iter().enumerate() {
+                ui.print_centered(top + 4 + index as u16, line);
+            }
+            ui.flush();
+        })?;
+
+        match nav(&settings.keys, key.code) {
+            Some(Nav::Left) => {
+                selected = (selected + pages.len() - 1) % pages.len();
+                ui.clear();
+            }
+            Some(Nav::Right) => {
+                selected = (selected + 1) % pages.len();
+                ui.clear();
+            }
+            Some(Nav::Back) | Some(Nav::Select) => return Ok(()),
+            _ => (),
+        }
+    }
+}
+
+// Most puzzles shown at once; the list scrolls to keep the selection in view
+const PUZZLE_ROWS: usize = 16;
+
+// Picks a puzzle to play, remembering the selection between games so a
+// failed puzzle is one key away from another attempt. None goes back.
+pub fn puzzle_menu(
+    ui: &mut Ui,
+    settings: &Settings,
+    puzzles: &[Result<Puzzle, String>],
+    selected: &mut usize,
+) -> Result<Option<usize>, Quit> {
+    let mut items: Vec<(String, bool)> = puzzles
+        .iter()
+        .map(|puzzle| match puzzle {
+            Ok(puzzle) => (
+                format!("{:<24}{:>16}", puzzle.name, puzzle.goal.describe()),
+                true,
+            ),
+            Err(err) => (err.clone(), false),
+        })
+        .collect();
+
+    // Nothing to pick from, but the menu still needs a row to stand on
+    if items.is_empty() {
+        items.push(("No puzzles found.".to_string(), false));
+    }
+    if *selected >= items.len() {
+        *selected = 0;
+    }
+
+    let folder = match puzzle::puzzles_dir() {
+        Some(dir) => format!("More puzzles go in {}", dir.display()),
+        None => String::new(),
+    };
+    ui.clear();
+
+    loop {
+        let first = (*selected + 1).saturating_sub(PUZZLE_ROWS);
+        let shown = &items[first..items.len().min(first + PUZZLE_ROWS)];
+
+        let key = ui.wait_key_redraw(REDRAW_INTERVAL, |ui| {
+            let top = menu_top(shown.len() + 2);
+            draw_title(ui, top, "PUZZLES");
+            draw_items(ui, top + 2, shown, *selected - first);
+            ui.print_centered(top + shown.len() as u16 + 3, &folder);
+            ui.flush();
+        })?;
+
+        match nav(&settings.keys, key.code) {
+            Some(Nav::Up) => {
+                *selected = (*selected + items.len() - 1) % items.len();
+                ui.clear();
+            }
+            Some(Nav::Down) => {
+                *selected = (*selected + 1) % items.len();
+                ui.clear();
+            }
+            Some(Nav::Select) if items[*selected].1 => return Ok(Some(*selected)),
+            Some(Nav::Back) => return Ok(None),
+            _ => (),
+        }
+    }
+}
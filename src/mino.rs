@@ -1,4 +1,4 @@
-#[derive(Copy, Clone, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum TetrominoType {
     I,
     O,
@@ -9,6 +9,36 @@ pub enum TetrominoType {
     L,
 }
 
+impl TetrominoType {
+    pub const ALL: [TetrominoType; 7] = [
+        TetrominoType::I,
+        TetrominoType::O,
+        TetrominoType::T,
+        TetrominoType::S,
+        TetrominoType::Z,
+        TetrominoType::J,
+        TetrominoType::L,
+    ];
+
+    pub fn letter(&self) -> char {
+        match self {
+            TetrominoType::I => 'I',
+            TetrominoType::O => 'O',
+            TetrominoType::T => 'T',
+            TetrominoType::S => 'S',
+            TetrominoType::Z => 'Z',
+            TetrominoType::J => 'J',
+            TetrominoType::L => 'L',
+        }
+    }
+
+    pub fn from_letter(letter: char) -> Option<TetrominoType> {
+        TetrominoType::ALL
+            .into_iter()
+            .find(|mino| mino.letter() == letter.to_ascii_uppercase())
+    }
+}
+
 #[derive(Copy, Clone, PartialEq)]
 pub enum PieceData {
     Small([[[bool; 3]; 3]; 4]),
@@ -16,7 +46,7 @@ pub enum PieceData {
     Large([[[bool; 4]; 4]; 4]),
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum GhostType {
     I,
     O,
@@ -74,8 +104,69 @@ impl Tetromino {
             },
         }
     }
+
+    // (col, row) of every filled cell in the current orientation, relative to
+    // the top left of the piece's bounding box.
+    pub fn cells(&self) -> Vec<(usize, usize)> {
+        fn filled<const W: usize, const H: usize>(data: &[[bool; W]; H]) -> Vec<(usize, usize)> {
+            let mut cells = Vec::with_capacity(4);
+            for (row, line) in data.iter().enumerate() {
+                for (col, cell) in line.iter().enumerate() {
+                    if *cell {
+                        cells.push((col, row));
+                    }
+                }
+            }
+            cells
+        }
+
+        match self.piece_data {
+            PieceData::Small(data) => filled(&data[self.orientation]),
+            PieceData::Medium(data) => filled(&data[self.orientation]),
+            PieceData::Large(data) => filled(&data[self.orientation]),
+        }
+    }
+
+    // The rows of the bounding box top down as bitmasks, bit n for column n,
+    // so a whole row can be checked against the field at once
+    pub fn row_masks(&self) -> &'static [u16; 4] {
+        &ROW_MASKS[self.tr_type as usize][self.orientation]
+    }
+}
+
+// Each row of each orientation as a bitmask, bit n for column n of the
+// bounding box
+const fn row_masks<const W: usize, const H: usize>(data: &[[[bool; W]; H]; 4]) -> [[u16; 4]; 4] {
+    let mut masks = [[0; 4]; 4];
+    let mut orientation = 0;
+    while orientation < 4 {
+        let mut row = 0;
+        while row < H {
+            let mut col = 0;
+            while col < W {
+                if data[orientation][row][col] {
+                    masks[orientation][row] |= 1 << col;
+                }
+                col += 1;
+            }
+            row += 1;
+        }
+        orientation += 1;
+    }
+    masks
 }
 
+// Indexed like TetrominoType::ALL
+const ROW_MASKS: [[[u16; 4]; 4]; 7] = [
+    row_masks(&I_MINO_DATA),
+    row_masks(&O_MINO_DATA),
+    row_masks(&T_MINO_DATA),
+    row_masks(&S_MINO_DATA),
+    row_masks(&Z_MINO_DATA),
+    row_masks(&J_MINO_DATA),
+    row_masks(&L_MINO_DATA),
+];
+
 pub const I_MINO_DATA: [[[bool; 4]; 4]; 4] = [
     [
         [false, false, false, false],
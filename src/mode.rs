@@ -0,0 +1,495 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::board::{Board, Snapshot};
+use crate::bot::Bot;
+use crate::constants::{HEIGHT, WIDTH};
+use crate::demo::Demo;
+use crate::dig::Dig;
+use crate::input::InputEvent;
+use crate::position::Position;
+use crate::settings::Settings;
+use crate::survival::Survival;
+use crate::trainer::Trainer;
+use crate::versus::Versus;
+
+pub const SPRINT_LINES: usize = 40;
+
+pub const MARATHON_LEVELS: usize = 15;
+pub const LINES_PER_LEVEL: usize = 10;
+
+pub const ULTRA_MINUTES: [u64; 2] = [2, 3];
+// Past this the guideline curve is already dropping pieces instantly
+const ULTRA_MAX_LEVEL: usize = 20;
+
+// How games of a mode are compared on the leaderboard
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Ranking {
+    // Races to a goal, where only finished runs count
+    FastestTime,
+    LongestTime,
+    HighestScore,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum GameMode {
+    Marathon,
+    Sprint,
+    Ultra,
+    Zen,
+    Dig,
+    Survival,
+    Puzzle,
+    Trainer,
+    Versus,
+    Demo,
+    Replays,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 11] = [
+        GameMode::Marathon,
+        GameMode::Sprint,
+        GameMode::Ultra,
+        GameMode::Zen,
+        GameMode::Dig,
+        GameMode::Survival,
+        GameMode::Puzzle,
+        GameMode::Trainer,
+        GameMode::Versus,
+        GameMode::Demo,
+        GameMode::Replays,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::Marathon => "Marathon",
+            GameMode::Sprint => "Sprint",
+            GameMode::Ultra => "Ultra",
+            GameMode::Zen => "Zen",
+            GameMode::Dig => "Dig",
+            GameMode::Survival => "Survival",
+            GameMode::Puzzle => "Puzzle",
+            GameMode::Trainer => "PC Trainer",
+            GameMode::Versus => "Versus",
+            GameMode::Demo => "Watch Bot",
+            GameMode::Replays => "Replays",
+        }
+    }
+
+    pub fn ranking(&self) -> Ranking {
+        match self {
+            GameMode::Sprint | GameMode::Dig => Ranking::FastestTime,
+            GameMode::Survival => Ranking::LongestTime,
+            _ => Ranking::HighestScore,
+        }
+    }
+
+    pub fn available(&self) -> bool {
+        !matches!(self, GameMode::Replays)
+    }
+
+    // Every puzzle is its own challenge, trainer runs have no end and games
+    // against or by the bot depend on its speed, so none of them has games to
+    // compare on a leaderboard
+    pub fn recorded(&self) -> bool {
+        !matches!(
+            self,
+            GameMode::Puzzle | GameMode::Trainer | GameMode::Versus | GameMode::Demo
+        )
+    }
+}
+
+// Seconds per row from the guideline gravity curve
+pub fn gravity_for_level(level: usize) -> Duration {
+    let level = level.max(1) as f64 - 1.0;
+    Duration::from_secs_f64((0.8 - level * 0.007).powf(level))
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GameEnd {
+    // The mode's goal was reached
+    Finished,
+    ToppedOut,
+    // A timed mode ran out of time, which is its goal
+    TimeUp,
+    // The pieces ran out before the goal was reached
+    Failed,
+    // The opponent topped out first
+    Won,
+    // The opponent's connection dropped before either side topped out
+    Disconnected,
+    // The two copies of a game played over the network stopped agreeing
+    Desynced,
+}
+
+impl GameEnd {
+    pub fn title(&self) -> &'static str {
+        match self {
+            GameEnd::Finished => "FINISHED",
+            GameEnd::ToppedOut => "GAME OVER",
+            GameEnd::TimeUp => "TIME UP",
+            GameEnd::Failed => "FAILED",
+            GameEnd::Won => "YOU WIN",
+            GameEnd::Disconnected => "DISCONNECTED",
+            GameEnd::Desynced => "DESYNC",
+        }
+    }
+
+    // Whether the run counts as complete for records, as opposed to the
+    // stack topping out first
+    pub fn completed(&self) -> bool {
+        !matches!(
+            self,
+            GameEnd::ToppedOut | GameEnd::Failed | GameEnd::Disconnected | GameEnd::Desynced
+        )
+    }
+}
+
+// Drives the rules of a mode on top of the board: levels, gravity and when
+// the game is over.
+pub trait ModeController {
+    // Called once before the first piece spawns
+    fn setup(&mut self, _board: &mut Board) {}
+
+    // Called every frame after the board has updated
+    fn update(&mut self, board: &mut Board, elapsed: Duration) -> Option<GameEnd>;
+
+    // Mode specific lines shown above the stats panel
+    fn hud(&self, board: &Board, elapsed: Duration) -> Vec<String>;
+
+    // Length of a timed mode, so results report the limit rather than the
+    // frame the timer was noticed on
+    fn time_limit(&self) -> Option<Duration> {
+        None
+    }
+
+    // Extra lines for the results screen
+    fn results(&self, _board: &Board, _elapsed: Duration) -> Vec<String> {
+        Vec::new()
+    }
+
+    // Modes without an undo stack ignore these
+    fn undo(&mut self, _board: &mut Board) {}
+
+    fn redo(&mut self, _board: &mut Board) {}
+
+    // Back to the position the game started from
+    fn retry(&mut self, _board: &mut Board) {}
+
+    // A second board shown beside the player's, for modes with an opponent
+    fn opponent(&self) -> Option<&Board> {
+        None
+    }
+
+    // An opponent's board shown at half size where the second board would
+    // go, just the stack
+    fn small_opponent(&self) -> Option<&Board> {
+        None
+    }
+
+    // The seed the game has to be dealt from, for modes that agree on one
+    // with an opponent
+    fn seed(&self) -> Option<u64> {
+        None
+    }
+
+    // Takes a gameplay key for the mode to play when it chooses, like on a
+    // tick agreed with another player, rather than the board straight away.
+    // Returns whether it did.
+    fn take_input(&mut self, _input: InputEvent) -> bool {
+        false
+    }
+
+    // Whether the mode moves the board's clock on itself rather than the
+    // frame loop doing it
+    fn owns_clock(&self) -> bool {
+        false
+    }
+
+    // Whether the mode moves the pieces itself, leaving the player's keys
+    // only to pause and quit
+    fn plays_itself(&self) -> bool {
+        false
+    }
+}
+
+// The controller for a game of `mode` set up from the settings alone. None
+// for puzzles, which need the puzzle picked from their menu, and for modes
+// that cannot be played yet.
+pub fn controller(mode: GameMode, settings: &Settings) -> Option<Box<dyn ModeController>> {
+    let controller: Box<dyn ModeController> = match mode {
+        GameMode::Marathon => Box::new(Marathon::new(
+            settings.marathon_start_level,
+            settings.marathon_endless,
+        )),
+        GameMode::Sprint => Box::new(Sprint),
+        GameMode::Ultra => Box::new(Ultra::new(Duration::from_secs(settings.ultra_minutes * 60))),
+        GameMode::Zen => Box::new(Zen::default()),
+        GameMode::Dig => Box::new(Dig::new(
+            settings.dig_rows,
+            settings.dig_repeat_holes,
+            settings.dig_messiness,
+        )),
+        GameMode::Survival => Box::new(Survival::default()),
+        GameMode::Trainer => Box::new(Trainer::new(settings.pc_opener, settings.pc_auto_restart)),
+        GameMode::Versus => Box::new(Versus::new(Bot::from_settings(settings))),
+        GameMode::Demo => Box::new(Demo::new(Bot::from_settings(settings))),
+        GameMode::Puzzle | GameMode::Replays => return None,
+    };
+    Some(controller)
+}
+
+// A board dealt from `seed` with the mode set up and the first piece out,
+// ready for the first frame whether anyone is watching or not
+pub fn start_board(
+    controller: &mut dyn ModeController,
+    seed: u64,
+    start: Option<&Position>,
+) -> Board {
+    let mut board = Board::with_seed((WIDTH, HEIGHT), seed);
+    controller.setup(&mut board);
+    if let Some(position) = start {
+        board.load_position(position);
+    }
+    board.new_tetromino();
+    board
+}
+
+pub struct Sprint;
+
+impl ModeController for Sprint {
+    fn update(&mut self, board: &mut Board, _elapsed: Duration) -> Option<GameEnd> {
+        if board.topped_out {
+            Some(GameEnd::ToppedOut)
+        } else if board.lines_cleared >= SPRINT_LINES {
+            Some(GameEnd::Finished)
+        } else {
+            None
+        }
+    }
+
+    fn hud(&self, board: &Board, _elapsed: Duration) -> Vec<String> {
+        vec![format!(
+            "LEFT:    {}",
+            SPRINT_LINES.saturating_sub(board.lines_cleared)
+        )]
+    }
+}
+
+pub struct Marathon {
+    start_level: usize,
+    endless: bool,
+    applied_level: usize,
+}
+
+impl Marathon {
+    pub fn new(start_level: usize, endless: bool) -> Self {
+        Marathon {
+            start_level: start_level.clamp(1, MARATHON_LEVELS),
+            endless,
+            applied_level: 0,
+        }
+    }
+
+    // Starting higher skips the early levels, but the level still follows the
+    // line count once it catches up.
+    fn level_for(&self, lines: usize) -> usize {
+        let level = (1 + lines / LINES_PER_LEVEL).max(self.start_level);
+        if self.endless {
+            level
+        } else {
+            level.min(MARATHON_LEVELS)
+        }
+    }
+}
+
+impl ModeController for Marathon {
+    fn update(&mut self, board: &mut Board, _elapsed: Duration) -> Option<GameEnd> {
+        if board.topped_out {
+            return Some(GameEnd::ToppedOut);
+        }
+
+        if !self.endless && board.lines_cleared >= MARATHON_LEVELS * LINES_PER_LEVEL {
+            return Some(GameEnd::Finished);
+        }
+
+        let level = self.level_for(board.lines_cleared);
+        if level != self.applied_level {
+            self.applied_level = level;
+            board.level = level;
+            board.set_gravity(gravity_for_level(level));
+        }
+
+        None
+    }
+
+    fn hud(&self, board: &Board, _elapsed: Duration) -> Vec<String> {
+        let goal = if self.endless {
+            format!("LINES:   {}", board.lines_cleared)
+        } else {
+            format!(
+                "GOAL:    {}/{}",
+                board.lines_cleared,
+                MARATHON_LEVELS * LINES_PER_LEVEL
+            )
+        };
+
+        vec![format!("LEVEL:   {}", board.level), goal]
+    }
+}
+
+pub struct Ultra {
+    duration: Duration,
+    applied_level: usize,
+}
+
+impl Ultra {
+    pub fn new(duration: Duration) -> Self {
+        Ultra {
+            duration,
+            applied_level: 0,
+        }
+    }
+
+    // Grows with the square of the time played, so each level comes sooner
+    // than the last: level 4 after a minute, 13 after two.
+    fn level_at(elapsed: Duration) -> usize {
+        let secs = elapsed.as_secs() as usize;
+        (1 + secs * secs / 1200).min(ULTRA_MAX_LEVEL)
+    }
+}
+
+impl ModeController for Ultra {
+    fn update(&mut self, board: &mut Board, elapsed: Duration) -> Option<GameEnd> {
+        if board.topped_out {
+            return Some(GameEnd::ToppedOut);
+        }
+
+        if elapsed >= self.duration {
+            return Some(GameEnd::TimeUp);
+        }
+
+        let level = Self::level_at(elapsed);
+        if level != self.applied_level {
+            self.applied_level = level;
+            board.level = level;
+            board.set_gravity(gravity_for_level(level));
+        }
+
+        None
+    }
+
+    fn hud(&self, board: &Board, elapsed: Duration) -> Vec<String> {
+        let left = self.duration.saturating_sub(elapsed).as_secs();
+        vec![
+            format!("LEFT:    {}:{:02}", left / 60, left % 60),
+            format!("LEVEL:   {}", board.level),
+        ]
+    }
+
+    fn time_limit(&self) -> Option<Duration> {
+        Some(self.duration)
+    }
+}
+
+// Snapshots of the board after each placement, for modes that can step
+// back and forth through the pieces played.
+#[derive(Default)]
+pub struct History {
+    // Position after the latest placement, or the start of the game
+    current: Option<Snapshot>,
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    placed: usize,
+}
+
+impl History {
+    // Called every frame; returns whether a new placement was recorded
+    pub fn record(&mut self, board: &Board) -> bool {
+        if self.current.is_some() && board.pieces_placed == self.placed {
+            return false;
+        }
+
+        if let Some(previous) = self.current.take() {
+            self.undo.push(previous);
+            self.redo.clear();
+        }
+        self.current = Some(board.snapshot());
+        self.placed = board.pieces_placed;
+        true
+    }
+
+    pub fn undo(&mut self, board: &mut Board) -> bool {
+        let Some(previous) = self.undo.pop() else {
+            return false;
+        };
+        self.redo.extend(self.current.replace(previous.clone()));
+        board.restore(previous);
+        self.placed = board.pieces_placed;
+        true
+    }
+
+    pub fn redo(&mut self, board: &mut Board) -> bool {
+        let Some(next) = self.redo.pop() else {
+            return false;
+        };
+        self.undo.extend(self.current.replace(next.clone()));
+        board.restore(next);
+        self.placed = board.pieces_placed;
+        true
+    }
+
+    // Puts back the first position recorded and forgets everything since
+    pub fn rewind(&mut self, board: &mut Board) {
+        let first = self.undo.drain(..).next().or(self.current.take());
+        self.redo.clear();
+
+        if let Some(first) = first {
+            board.restore(first.clone());
+            self.current = Some(first);
+            self.placed = board.pieces_placed;
+        }
+    }
+
+    pub fn hud_line(&self) -> String {
+        format!("UNDO:    {}/{}", self.undo.len(), self.redo.len())
+    }
+}
+
+// Free play for working out openers: nothing falls, pieces only lock on a
+// hard drop, the stack can never top out and every placement can be undone.
+#[derive(Default)]
+pub struct Zen {
+    history: History,
+}
+
+impl ModeController for Zen {
+    fn setup(&mut self, board: &mut Board) {
+        board.set_gravity(Duration::MAX);
+        board.auto_lock = false;
+        board.can_top_out = false;
+    }
+
+    fn update(&mut self, board: &mut Board, _elapsed: Duration) -> Option<GameEnd> {
+        self.history.record(board);
+        None
+    }
+
+    fn hud(&self, board: &Board, _elapsed: Duration) -> Vec<String> {
+        vec![
+            format!("LINES:   {}", board.lines_cleared),
+            self.history.hud_line(),
+        ]
+    }
+
+    fn undo(&mut self, board: &mut Board) {
+        self.history.undo(board);
+    }
+
+    fn redo(&mut self, board: &mut Board) {
+        self.history.redo(board);
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::HashSet;
+
+use crate::board::{shift_mask, Board, RotationDirection, Stack};
+use crate::mino::Tetromino;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Move {
+    Left,
+    Right,
+    RotateCW,
+    RotateCCW,
+    // Straight down as far as the piece goes, without locking
+    SoftDrop,
+    HardDrop,
+}
+
+impl Move {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Move::Left => "←",
+            Move::Right => "→",
+            Move::RotateCW => "↻",
+            Move::RotateCCW => "↺",
+            Move::SoftDrop => "↓",
+            Move::HardDrop => "⤓",
+        }
+    }
+}
+
+// Every input a search may use, so tucks and spins are found
+pub const ALL_MOVES: [Move; 5] = [
+    Move::Left,
+    Move::Right,
+    Move::RotateCW,
+    Move::RotateCCW,
+    Move::SoftDrop,
+];
+
+// Board cells covered by `mino` at (x, y), sorted so placements of symmetric
+// pieces compare equal whatever orientation reached them.
+pub fn placement_cells(mino: &Tetromino, x: i32, y: i32) -> Vec<(i32, i32)> {
+    let mut cells: Vec<(i32, i32)> = mino
+        .cells()
+        .into_iter()
+        .map(|(col, row)| (x + col as i32, y + row as i32))
+        .collect();
+    cells.sort();
+    cells
+}
+
+// Where a piece can lock, in the orientation it locks in
+#[derive(Clone)]
+pub struct Placement {
+    pub mino: Tetromino,
+    pub x: i32,
+    pub y: i32,
+    // Locks straight after a rotation, so a T here can score as a spin
+    pub spin: bool,
+    // Fewest inputs from spawn that reach it, ending with the hard drop
+    pub path: Vec<Move>,
+}
+
+impl Placement {
+    pub fn cells(&self) -> Vec<(i32, i32)> {
+        placement_cells(&self.mino, self.x, self.y)
+    }
+}
+
+// One step of `mv` from (x, y), or None when it is blocked
+fn step<S: Stack>(
+    stack: &S,
+    mino: &Tetromino,
+    x: i32,
+    y: i32,
+    mv: Move,
+) -> Option<(Tetromino, i32, i32)> {
+    match mv {
+        Move::Left | Move::Right => {
+            let dx = if mv == Move::Left { -1 } else { 1 };
+            (!stack.collides(mino, x + dx, y)).then(|| (mino.clone(), x + dx, y))
+        }
+        Move::RotateCW => stack.kick(mino, x, y, RotationDirection::Clockwise),
+        Move::RotateCCW => stack.kick(mino, x, y, RotationDirection::CounterClockwise),
+        Move::SoftDrop => {
+            let landed = stack.drop_y(mino, x, y);
+            (landed != y).then(|| (mino.clone(), x, landed))
+        }
+        Move::HardDrop => None,
+    }
+}
+
+// Every distinct place `mino` can lock on `stack` when it starts at `spawn`,
+// moving only with `moves`. Collisions and kicks are the stack's own, and the
+// search is breadth first, so each placement comes with its shortest path.
+// Orientations that cover the same cells count once, unless only one of them
+// locks as a spin.
+pub fn placements<S: Stack>(
+    stack: &S,
+    mino: &Tetromino,
+    spawn: (i32, i32),
+    moves: &[Move],
+) -> Vec<Placement> {
+    let mut visited = Visited::new(stack);
+    let mut found = HashSet::new();
+    let mut placements = Vec::new();
+    // Every spot reached, in the order reached, with the spot and move it was
+    // reached from. The spots not yet looked at are the search queue.
+    let mut spots: Vec<Spot> = Vec::new();
+
+    visited.insert(spawn.0, spawn.1, mino.orientation);
+    spots.push(Spot {
+        mino: mino.clone(),
+        x: spawn.0,
+        y: spawn.1,
+        from: None,
+    });
+
+    let mut next = 0;
+    while next < spots.len() {
+        let (x, y) = (spots[next].x, spots[next].y);
+        let mino = spots[next].mino.clone();
+        let last = spots[next].from.map(|(_, mv)| mv);
+
+        let landed = stack.drop_y(&mino, x, y);
+        let spin = landed == y && matches!(last, Some(Move::RotateCW | Move::RotateCCW));
+
+        if found.insert((cell_key(&mino, x, landed), spin)) {
+            let mut path = path_to(&spots, next);
+            path.push(Move::HardDrop);
+            placements.push(Placement {
+                mino: mino.clone(),
+                x,
+                y: landed,
+                spin,
+                path,
+            });
+        }
+
+        for &mv in moves {
+            // Soft dropping lands where the hard drop above did
+            let moved = match mv {
+                Move::SoftDrop => (landed != y).then(|| (mino.clone(), x, landed)),
+                _ => step(stack, &mino, x, y, mv),
+            };
+            if let Some((moved, nx, ny)) = moved {
+                if visited.insert(nx, ny, moved.orientation) {
+                    spots.push(Spot {
+                        mino: moved,
+                        x: nx,
+                        y: ny,
+                        from: Some((next, mv)),
+                    });
+                }
+            }
+        }
+        next += 1;
+    }
+
+    placements
+}
+
+// Spots already reached, one flag for each column, row and orientation. A
+// piece's box can hang up to three columns off either side.
+struct Visited {
+    width: usize,
+    height: usize,
+    seen: Vec<bool>,
+}
+
+impl Visited {
+    fn new<S: Stack>(stack: &S) -> Self {
+        let (width, height) = (stack.width() + 6, stack.rows().len() + 4);
+        Visited {
+            width,
+            height,
+            seen: vec![false; width * height * 4],
+        }
+    }
+
+    // Whether (x, y, orientation) is new, marking it seen
+    fn insert(&mut self, x: i32, y: i32, orientation: usize) -> bool {
+        let (col, row) = ((x + 3) as usize, (y + 3) as usize);
+        if col >= self.width || row >= self.height {
+            return true;
+        }
+        let index = (orientation * self.height + row) * self.width + col;
+        !std::mem::replace(&mut self.seen[index], true)
+    }
+}
+
+struct Spot {
+    mino: Tetromino,
+    x: i32,
+    y: i32,
+    from: Option<(usize, Move)>,
+}
+
+// The moves that reached `spots[index]` from the first spot
+fn path_to(spots: &[Spot], mut index: usize) -> Vec<Move> {
+    let mut path = Vec::new();
+    while let Some((from, mv)) = spots[index].from {
+        path.push(mv);
+        index = from;
+    }
+    path.reverse();
+    path
+}
+
+// The cells `mino` covers at (x, y), as the top row and the masks of the rows
+// from there down. Orientations covering the same cells give the same key.
+fn cell_key(mino: &Tetromino, x: i32, y: i32) -> (i32, [u16; 4]) {
+    let masks = mino.row_masks();
+    let top = masks.iter().position(|mask| *mask != 0).unwrap_or(0);
+    let mut key = [0; 4];
+    for (row, mask) in masks[top..].iter().enumerate() {
+        key[row] = shift_mask(*mask, x, 16).unwrap_or(0);
+    }
+    (y + top as i32, key)
+}
+
+// Placements of the board's active piece from where it is now
+pub fn active_placements(board: &Board) -> Vec<Placement> {
+    match board.active() {
+        Some(mino) => placements(board, mino, board.active_at(), &ALL_MOVES),
+        None => Vec::new(),
+    }
+}
+
+// The fewest keys that take the active piece from where it is now to
+// `target`, landing with the same spin. None when it can no longer get there.
+pub fn route(board: &Board, target: &Placement) -> Option<Vec<Move>> {
+    let cells = target.cells();
+    active_placements(board)
+        .into_iter()
+        .find(|placement| placement.spin == target.spin && placement.cells() == cells)
+        .map(|placement| placement.path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::finesse;
+    use crate::mino::TetrominoType;
+    use crate::position::Position;
+
+    const HARD_DROPS: [Move; 4] = [Move::Left, Move::Right, Move::RotateCW, Move::RotateCCW];
+
+    fn board(field: &str, current: TetrominoType) -> Board {
+        let text = format!(
+            "hold -\ncurrent {}\nqueue -\nfield\n{}",
+            current.letter(),
+            field
+        );
+        let mut board = Board::with_seed((10, 20), 0);
+        board.load_position(&Position::from_text(&text).unwrap());
+        board.new_tetromino();
+        board
+    }
+
+    #[test]
+    fn counts_placements_on_an_empty_field() {
+        let expected = [
+            (TetrominoType::I, 17),
+            (TetrominoType::O, 9),
+            (TetrominoType::T, 34),
+            (TetrominoType::S, 17),
+            (TetrominoType::Z, 17),
+            (TetrominoType::J, 34),
+            (TetrominoType::L, 34),
+        ];
+
+        for (kind, count) in expected {
+            let board = board("..........\n", kind);
+            let found = placements(&board, &Tetromino::new(kind), board.spawn(), &HARD_DROPS);
+            assert_eq!(found.len(), count, "{:?}", kind);
+            assert!(found.iter().all(|placement| !placement.spin));
+        }
+    }
+
+    // The slot is under an overhang, so the T has to drop in upright and
+    // kick into it on the last rotation
+    #[test]
+    fn finds_a_t_spin_double_through_a_kick() {
+        let mut board = board("GG........\nG...GGGGGG\nGG.GGGGGGG\n", TetrominoType::T);
+        let floor = board.height as i32 - 1;
+        let slot = vec![(1, floor - 1), (2, floor - 1), (2, floor), (3, floor - 1)];
+
+        let placement = active_placements(&board)
+            .into_iter()
+            .find(|placement| placement.cells() == slot)
+            .expect("no path into the slot");
+        assert!(placement.spin);
+        assert_eq!(placement.mino.orientation, 2);
+        assert_eq!(
+            placement.path,
+            vec![
+                Move::Left,
+                Move::Left,
+                Move::RotateCW,
+                Move::SoftDrop,
+                Move::RotateCW,
+                Move::HardDrop
+            ]
+        );
+
+        // Finesse only grades what hard drops reach
+        let mino = Tetromino::new(TetrominoType::T);
+        assert_eq!(
+            finesse::optimal_inputs(&board, &mino, board.spawn(), &slot),
+            None
+        );
+
+        board.place(&placement);
+        assert_eq!(board.lines_cleared, 2);
+    }
+
+    #[test]
+    fn grades_finesse_on_the_fewest_inputs() {
+        let board = board("..........\n", TetrominoType::T);
+        let floor = board.height as i32 - 1;
+        let inputs = |kind: TetrominoType, target: Vec<(i32, i32)>| {
+            let mino = Tetromino::new(kind);
+            finesse::optimal_inputs(&board, &mino, board.spawn(), &target)
+                .expect("no path")
+                .len()
+        };
+
+        // Dropped where it spawns
+        let t = vec![(3, floor), (4, floor - 1), (4, floor), (5, floor)];
+        assert_eq!(inputs(TetrominoType::T, t), 1);
+        // Four taps to the left wall
+        let o = vec![(0, floor - 1), (0, floor), (1, floor - 1), (1, floor)];
+        assert_eq!(inputs(TetrominoType::O, o), 5);
+        // Upright an I stands in its third column, four taps from the wall
+        let i = (floor - 3..=floor).map(|y| (9, y)).collect();
+        assert_eq!(inputs(TetrominoType::I, i), 6);
+        // Turned twice rather than three times
+        let t = vec![(3, floor - 1), (4, floor - 1), (4, floor), (5, floor - 1)];
+        assert_eq!(inputs(TetrominoType::T, t), 3);
+    }
+}
@@ -0,0 +1,208 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+// Bumped whenever a message changes, so different builds refuse to play each
+// other rather than misread what they are sent
+pub const PROTOCOL_VERSION: u32 = 2;
+pub const DEFAULT_PORT: u16 = 7420;
+// How long the other side gets to answer while a game is being set up
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The keys that move a piece, which are all either side ever sends of its game
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Input {
+    Left,
+    Right,
+    RotateCw,
+    RotateCcw,
+    SoftDrop,
+    HardDrop,
+    Hold,
+}
+
+// Everything the two sides of a game say to each other, one JSON object to a
+// line. Each side opens with Hello, and once the versions match the host
+// picks the seed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    Hello {
+        version: u32,
+    },
+    // Both games are dealt from the same seed
+    Start {
+        seed: u64,
+    },
+    // The sender's keys for every tick before `through` not already sent,
+    // each with the tick it lands on. Ticks left out had none.
+    Inputs {
+        through: u64,
+        inputs: Vec<(u64, Input)>,
+    },
+    // What both boards hashed to after `tick`, the host's first
+    Hash {
+        tick: u64,
+        hash: u64,
+    },
+    // Why the sender will not play
+    Error {
+        reason: String,
+    },
+}
+
+fn invalid(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+// One end of a game between two players over TCP. Messages are read on a
+// thread of their own, so checking for them never holds up a frame.
+pub struct Connection {
+    stream: TcpStream,
+    messages: Receiver<Message>,
+    hosting: bool,
+    closed: bool,
+}
+
+impl Connection {
+    // Waits on `port` for someone to join, then deals the game
+    pub fn host(port: u16) -> io::Result<(Connection, u64)> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        let (stream, _) = listener.accept()?;
+        Self::handshake(stream, Some(rand::random()))
+    }
+
+    // Joins a game hosted at `addr`, given as host:port
+    pub fn join(addr: &str) -> io::Result<(Connection, u64)> {
+        let stream = TcpStream::connect(addr)?;
+        Self::handshake(stream, None)
+    }
+
+    // Agrees on the version and the seed, which the host deals and the
+    // other side waits to hear
+    fn handshake(mut stream: TcpStream, seed: Option<u64>) -> io::Result<(Connection, u64)> {
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        let mut read = || -> io::Result<Message> {
+            let mut line = String::new();
+            match reader.read_line(&mut line) {
+                Ok(0) => Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "the other side hung up",
+                )),
+                Ok(_) => serde_json::from_str(&line).map_err(io::Error::from),
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "the other side did not answer",
+                    ))
+                }
+                Err(err) => Err(err),
+            }
+        };
+
+        write_message(
+            &mut stream,
+            &Message::Hello {
+                version: PROTOCOL_VERSION,
+            },
+        )?;
+
+        match read()? {
+            Message::Hello { version } if version == PROTOCOL_VERSION => (),
+            Message::Hello { version } => {
+                let reason = format!(
+                    "the other side speaks protocol version {}, this is version {}",
+                    version, PROTOCOL_VERSION
+                );
+                let _ = write_message(
+                    &mut stream,
+                    &Message::Error {
+                        reason: reason.clone(),
+                    },
+                );
+                return Err(invalid(reason));
+            }
+            Message::Error { reason } => return Err(invalid(reason)),
+            other => return Err(invalid(format!("expected hello, got {:?}", other))),
+        }
+
+        let hosting = seed.is_some();
+        let seed = match seed {
+            Some(seed) => {
+                write_message(&mut stream, &Message::Start { seed })?;
+                seed
+            }
+            None => match read()? {
+                Message::Start { seed } => seed,
+                Message::Error { reason } => return Err(invalid(reason)),
+                other => return Err(invalid(format!("expected start, got {:?}", other))),
+            },
+        };
+
+        stream.set_read_timeout(None)?;
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for line in reader.lines().map_while(Result::ok) {
+                if let Ok(message) = serde_json::from_str(&line) {
+                    if tx.send(message).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        let connection = Connection {
+            stream,
+            messages: rx,
+            hosting,
+            closed: false,
+        };
+        Ok((connection, seed))
+    }
+
+    pub fn send(&mut self, message: &Message) {
+        if !self.closed && write_message(&mut self.stream, message).is_err() {
+            self.closed = true;
+        }
+    }
+
+    pub fn receive(&mut self) -> Option<Message> {
+        match self.messages.try_recv() {
+            Ok(message) => Some(message),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.closed = true;
+                None
+            }
+        }
+    }
+
+    // Whether this is the host's end, which goes first wherever the two
+    // sides need an order to agree on
+    pub fn hosting(&self) -> bool {
+        self.hosting
+    }
+
+    // Whether the other side has gone, once everything it sent is read
+    pub fn closed(&self) -> bool {
+        self.closed
+    }
+}
+
+fn write_message(stream: &mut TcpStream, message: &Message) -> io::Result<()> {
+    let line = serde_json::to_string(message).unwrap();
+    writeln!(stream, "{}", line).and_then(|_| stream.flush())
+}
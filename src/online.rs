@@ -0,0 +1,429 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+use crate::board::{Board, RotationDirection};
+use crate::constants::{HEIGHT, WIDTH};
+use crate::input::InputEvent;
+use crate::mode::{GameEnd, ModeController};
+use crate::net::{Connection, Input, Message};
+use crate::versus::Side;
+
+// Steps of the shared game clock per second
+pub const TICK_RATE: u64 = 60;
+// Ticks between a key being pressed and it landing on both boards, which
+// gives it time to reach the other side
+pub const INPUT_DELAY: u64 = 4;
+// How far ahead of the boards a side may send its keys before it waits for
+// the other to catch up
+const MAX_LEAD: u64 = 12;
+// Ticks between board hashes
+const HASH_TICKS: u64 = 60;
+
+// Game time at the end of `tick`, worked out the same way on both sides
+fn tick_time(tick: u64) -> Duration {
+    Duration::from_nanos((tick + 1) * 1_000_000_000 / TICK_RATE)
+}
+
+fn ticks_in(time: Duration) -> u64 {
+    (time.as_nanos() * TICK_RATE as u128 / 1_000_000_000) as u64
+}
+
+fn input_for(event: InputEvent) -> Option<Input> {
+    match event {
+        InputEvent::MoveLeft => Some(Input::Left),
+        InputEvent::MoveRight => Some(Input::Right),
+        InputEvent::RotateCW => Some(Input::RotateCw),
+        InputEvent::RotateCCW => Some(Input::RotateCcw),
+        InputEvent::SoftDrop => Some(Input::SoftDrop),
+        InputEvent::HardDrop => Some(Input::HardDrop),
+        InputEvent::Hold => Some(Input::Hold),
+        _ => None,
+    }
+}
+
+fn press(board: &mut Board, input: Input) {
+    match input {
+        Input::Left => board.move_tetromino((-1, 0)),
+        Input::Right => board.move_tetromino((1, 0)),
+        Input::RotateCw => board.rotate(RotationDirection::Clockwise),
+        Input::RotateCcw => board.rotate(RotationDirection::CounterClockwise),
+        Input::SoftDrop => board.soft_harddrop(),
+        Input::HardDrop => board.hard_drop(),
+        Input::Hold => board.hold_piece(),
+    }
+}
+
+// Plays one tick on both boards, host first and guest second: the keys,
+// then the clock, then the garbage, with the host's side first each time.
+// Both sides play every tick this way.
+fn play_tick(tick: u64, boards: [&mut Board; 2], sides: [&mut Side; 2], inputs: [Vec<Input>; 2]) {
+    let [host, guest] = boards;
+    let [host_side, guest_side] = sides;
+    let [host_inputs, guest_inputs] = inputs;
+    let now = tick_time(tick);
+
+    for input in host_inputs {
+        press(host, input);
+    }
+    for input in guest_inputs {
+        press(guest, input);
+    }
+    host.update(now);
+    guest.update(now);
+
+    let sent = host_side.exchange(host);
+    guest_side.garbage.receive(sent);
+    let sent = guest_side.exchange(guest);
+    host_side.garbage.receive(sent);
+}
+
+// What the two sides compare to know their games still match
+fn race_hash(host: &Board, guest: &Board) -> u64 {
+    host.state_hash() ^ guest.state_hash().rotate_left(1)
+}
+
+// Keys waiting for their tick, and the first tick not yet heard about
+#[derive(Default)]
+struct Inputs {
+    pending: BTreeMap<u64, Vec<Input>>,
+    through: u64,
+}
+
+impl Inputs {
+    fn take(&mut self, tick: u64) -> Vec<Input> {
+        self.pending.remove(&tick).unwrap_or_default()
+    }
+}
+
+// A race against someone on another terminal, played in lockstep. Both sides
+// run both boards from the same seed and trade only their keys, each landing
+// a few ticks after it was pressed. A tick is only played once both sides'
+// keys for it are in, so garbage crosses on the same tick everywhere and the
+// two copies of the game never differ unless something is wrong, which the
+// board hashes they swap would show.
+pub struct Online {
+    connection: Connection,
+    seed: u64,
+    opponent: Board,
+    player: Side,
+    rival: Side,
+    ours: Inputs,
+    theirs: Inputs,
+    // Keys pressed since the last ones were sent
+    pressed: Vec<Input>,
+    // The next tick to play
+    tick: u64,
+    // How much of the game clock has passed for sending keys. It stops
+    // while the boards wait for the other side, so both slow down together.
+    clock: Duration,
+    last_elapsed: Duration,
+    // Hashes each side worked out, until the other side's arrives
+    hashes: VecDeque<(u64, u64)>,
+    their_hashes: BTreeMap<u64, u64>,
+    desync: Option<u64>,
+}
+
+impl Online {
+    pub fn new(connection: Connection, seed: u64) -> Self {
+        Online {
+            connection,
+            seed,
+            opponent: Board::with_seed((WIDTH, HEIGHT), seed),
+            player: Side::new(seed),
+            rival: Side::new(seed),
+            ours: Inputs {
+                pending: BTreeMap::new(),
+                through: INPUT_DELAY,
+            },
+            theirs: Inputs {
+                pending: BTreeMap::new(),
+                through: INPUT_DELAY,
+            },
+            pressed: Vec::new(),
+            tick: 0,
+            clock: Duration::ZERO,
+            last_elapsed: Duration::ZERO,
+            hashes: VecDeque::new(),
+            their_hashes: BTreeMap::new(),
+            desync: None,
+        }
+    }
+
+    fn handle(&mut self, message: Message) {
+        match message {
+            Message::Inputs { through, inputs } => {
+                for (tick, input) in inputs {
+                    self.theirs.pending.entry(tick).or_default().push(input);
+                }
+                self.theirs.through = self.theirs.through.max(through);
+            }
+            Message::Hash { tick, hash } => {
+                self.their_hashes.insert(tick, hash);
+            }
+            _ => (),
+        }
+    }
+
+    // Sends the keys pressed so far for the tick they land on, once the game
+    // clock reaches a tick not yet sent
+    fn send_inputs(&mut self, elapsed: Duration) {
+        if self.ours.through < self.tick + MAX_LEAD {
+            self.clock += elapsed.saturating_sub(self.last_elapsed);
+        }
+        self.last_elapsed = elapsed;
+
+        let due = (ticks_in(self.clock) + INPUT_DELAY).min(self.tick + MAX_LEAD);
+        if due <= self.ours.through {
+            return;
+        }
+
+        let lands = due - 1;
+        let inputs: Vec<(u64, Input)> =
+            self.pressed.drain(..).map(|input| (lands, input)).collect();
+        if !inputs.is_empty() {
+            self.ours
+                .pending
+                .insert(lands, inputs.iter().map(|(_, input)| *input).collect());
+        }
+        self.ours.through = due;
+        self.connection.send(&Message::Inputs {
+            through: due,
+            inputs,
+        });
+    }
+
+    // Plays the next tick and sends the hash when one is due
+    fn step(&mut self, board: &mut Board) {
+        let tick = self.tick;
+        let ours = self.ours.take(tick);
+        let theirs = self.theirs.take(tick);
+
+        let (host, guest, host_side, guest_side, inputs) = if self.connection.hosting() {
+            (
+                board,
+                &mut self.opponent,
+                &mut self.player,
+                &mut self.rival,
+                [ours, theirs],
+            )
+        } else {
+            (
+                &mut self.opponent,
+                board,
+                &mut self.rival,
+                &mut self.player,
+                [theirs, ours],
+            )
+        };
+        play_tick(
+            tick,
+            [&mut *host, &mut *guest],
+            [host_side, guest_side],
+            inputs,
+        );
+
+        if (tick + 1).is_multiple_of(HASH_TICKS) {
+            let hash = race_hash(host, guest);
+            self.hashes.push_back((tick, hash));
+            self.connection.send(&Message::Hash { tick, hash });
+        }
+
+        self.tick += 1;
+    }
+
+    fn check_hashes(&mut self) {
+        while let Some(&(tick, hash)) = self.hashes.front() {
+            let Some(theirs) = self.their_hashes.remove(&tick) else {
+                break;
+            };
+            self.hashes.pop_front();
+            if theirs != hash {
+                self.desync.get_or_insert(tick);
+            }
+        }
+    }
+
+    // Whether the boards are held up waiting on the other side's keys
+    fn waiting(&self) -> bool {
+        self.tick >= self.theirs.through && self.ours.through > self.tick
+    }
+}
+
+impl ModeController for Online {
+    fn setup(&mut self, board: &mut Board) {
+        self.opponent = Board::with_seed((board.width, board.height - 10), self.seed);
+        self.opponent.new_tetromino();
+    }
+
+    fn update(&mut self, board: &mut Board, elapsed: Duration) -> Option<GameEnd> {
+        while let Some(message) = self.connection.receive() {
+            self.handle(message);
+        }
+
+        self.send_inputs(elapsed);
+
+        while self.tick < self.ours.through && self.tick < self.theirs.through {
+            self.step(board);
+
+            if board.topped_out {
+                return Some(GameEnd::ToppedOut);
+            } else if self.opponent.topped_out {
+                return Some(GameEnd::Won);
+            }
+        }
+
+        self.check_hashes();
+        if self.desync.is_some() {
+            Some(GameEnd::Desynced)
+        } else if self.connection.closed() && self.waiting() {
+            Some(GameEnd::Disconnected)
+        } else {
+            None
+        }
+    }
+
+    fn hud(&self, _board: &Board, _elapsed: Duration) -> Vec<String> {
+        let mut hud = vec![
+            format!("SENT:    {}", self.player.sent),
+            format!("GARBAGE: {}", self.player.garbage.pending()),
+        ];
+        if self.waiting() {
+            hud.push("WAITING...".to_string());
+        }
+        hud
+    }
+
+    fn results(&self, _board: &Board, _elapsed: Duration) -> Vec<String> {
+        let mut results = vec![
+            format!("Sent     {:>10}", self.player.sent),
+            format!("Received {:>10}", self.player.received),
+            format!("Opp sent {:>10}", self.rival.sent),
+        ];
+        if let Some(tick) = self.desync {
+            results.push(format!("Desync at tick {}", tick));
+        }
+        results
+    }
+
+    fn small_opponent(&self) -> Option<&Board> {
+        Some(&self.opponent)
+    }
+
+    fn seed(&self) -> Option<u64> {
+        Some(self.seed)
+    }
+
+    fn take_input(&mut self, input: InputEvent) -> bool {
+        match input_for(input) {
+            Some(input) => {
+                self.pressed.push(input);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn owns_clock(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::{self, Weights};
+    use crate::movegen::Move;
+
+    const SEED: u64 = 7;
+    const TICKS: u64 = 4 * HASH_TICKS;
+    // Ticks between the pieces each board places
+    const PIECE_TICKS: u64 = 10;
+
+    // Both boards and sides as one side of the connection sees them
+    struct Race {
+        boards: [Board; 2],
+        sides: [Side; 2],
+    }
+
+    impl Race {
+        fn new() -> Self {
+            let board = || {
+                let mut board = Board::with_seed((WIDTH, HEIGHT), SEED);
+                board.new_tetromino();
+                board
+            };
+            Race {
+                boards: [board(), board()],
+                sides: [Side::new(SEED), Side::new(SEED)],
+            }
+        }
+
+        fn play(&mut self, tick: u64, inputs: [Vec<Input>; 2]) -> Option<u64> {
+            let [host, guest] = &mut self.boards;
+            let [host_side, guest_side] = &mut self.sides;
+            play_tick(tick, [host, guest], [host_side, guest_side], inputs);
+
+            let [host, guest] = &self.boards;
+            (tick + 1)
+                .is_multiple_of(HASH_TICKS)
+                .then(|| race_hash(host, guest))
+        }
+    }
+
+    // The bot's keys for the active piece, so the boards clear lines and
+    // trade garbage
+    fn keys(board: &Board) -> Vec<Input> {
+        let Some(plan) = bot::plan(board, &Weights::default()) else {
+            return vec![Input::HardDrop];
+        };
+        let hold = plan.hold.then_some(Input::Hold);
+        let moves = plan.placement.path.iter().map(|mv| match mv {
+            Move::Left => Input::Left,
+            Move::Right => Input::Right,
+            Move::RotateCW => Input::RotateCw,
+            Move::RotateCCW => Input::RotateCcw,
+            Move::SoftDrop => Input::SoftDrop,
+            Move::HardDrop => Input::HardDrop,
+        });
+        hold.into_iter().chain(moves).collect()
+    }
+
+    // Plays the same race on both sides, with `extra` pressed on the guest's
+    // board after the bot's keys on one side only, and returns each side's
+    // hashes
+    fn race(extra: Option<(u64, Input)>) -> (Vec<u64>, Vec<u64>) {
+        let (mut here, mut there) = (Race::new(), Race::new());
+        let (mut ours, mut theirs) = (Vec::new(), Vec::new());
+
+        for tick in 0..TICKS {
+            let inputs = if tick % PIECE_TICKS == 0 {
+                [keys(&here.boards[0]), keys(&here.boards[1])]
+            } else {
+                [Vec::new(), Vec::new()]
+            };
+            let mut their_inputs = inputs.clone();
+            if let Some((_, input)) = extra.filter(|(at, _)| *at == tick) {
+                their_inputs[1].push(input);
+            }
+
+            ours.extend(here.play(tick, inputs));
+            theirs.extend(there.play(tick, their_inputs));
+        }
+        assert!(here.sides.iter().any(|side| side.sent > 0));
+        (ours, theirs)
+    }
+
+    #[test]
+    fn same_inputs_keep_hashes_equal() {
+        let (ours, theirs) = race(None);
+        assert_eq!(ours.len(), (TICKS / HASH_TICKS) as usize);
+        assert_eq!(ours, theirs);
+    }
+
+    #[test]
+    fn diverging_inputs_change_the_hash() {
+        let (ours, theirs) = race(Some((HASH_TICKS + PIECE_TICKS, Input::HardDrop)));
+        assert_eq!(ours[0], theirs[0]);
+        assert_ne!(ours[1..], theirs[1..]);
+    }
+}
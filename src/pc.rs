@@ -0,0 +1,795 @@
+use std::collections::HashMap;
+use std::ops::{BitAnd, BitOr, Not};
+
+use crate::board::{rotation, RotationDirection, Status};
+use crate::mino::{Tetromino, TetrominoType};
+use crate::position::Position;
+
+// Rows a perfect clear is searched over unless the rules say otherwise
+pub const PC_ROWS: usize = 4;
+// The tallest clear a field of 64 bits holds
+pub const MAX_PC_ROWS: usize = 6;
+
+const WIDTH: usize = 10;
+const FULL_ROW: u64 = (1 << WIDTH) - 1;
+// Empty rows over the clear for pieces to come in and turn, and where in
+// them they appear
+const HEADROOM: usize = 6;
+const SPAWN: (i32, i32) = (3, 2);
+// Pieces tried while looking for a way to fill a field before giving up and
+// searching it anyway
+const TILING_LIMIT: usize = 20;
+
+// One piece of a solution. Cells are (column, row) with rows counted up from
+// the floor, on the field as it stands when the piece goes down.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Placement {
+    pub kind: TetrominoType,
+    pub cells: [(usize, usize); 4],
+}
+
+// What counts as a solution and which pieces may go down when
+#[derive(Copy, Clone, Debug)]
+pub struct Rules {
+    // Lines the clear takes, up to MAX_PC_ROWS
+    pub rows: usize,
+    pub hold: bool,
+    // A piece kept back until this many others are down, like a T saved
+    // for the clear
+    pub save: Option<(TetrominoType, usize)>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            rows: PC_ROWS,
+            hold: true,
+            save: None,
+        }
+    }
+}
+
+// The bottom rows of a field as bits, ten to a row from the floor up with
+// the leftmost column lowest
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct Field(u64);
+
+impl Field {
+    // None when the field is not ten wide or is filled above `rows`
+    fn from_position(position: &Position, rows: usize) -> Option<Field> {
+        let mut bits = 0;
+        for (row, cells) in position.field.iter().rev().enumerate() {
+            if cells.len() != WIDTH {
+                return None;
+            }
+
+            for (x, cell) in cells.iter().enumerate() {
+                if matches!(cell, Status::FillType(_) | Status::Garbage) {
+                    if row >= rows {
+                        return None;
+                    }
+                    bits |= 1 << (row * WIDTH + x);
+                }
+            }
+        }
+        Some(Field(bits))
+    }
+
+    fn empty_cells(&self, rows: usize) -> usize {
+        rows * WIDTH - self.0.count_ones() as usize
+    }
+
+    // Every run of columns between full ones has to be filled by whole
+    // pieces, so its empty cells must come in fours.
+    fn splittable(&self, rows: usize) -> bool {
+        let mut run = 0;
+        for x in 0..WIDTH {
+            let filled = (0..rows)
+                .filter(|row| self.0 & (1 << (row * WIDTH + x)) != 0)
+                .count();
+            if filled == rows {
+                if run % 4 != 0 {
+                    return false;
+                }
+                run = 0;
+            } else {
+                run += rows - filled;
+            }
+        }
+        run % 4 == 0
+    }
+
+    // Whether every empty cell could still be covered by one of `shapes` lying
+    // on cells that are empty now. A piece that goes down later sits on cells
+    // empty now too, only with any rows cleared in the meantime taken out
+    // from between its own, so its rows may land on any rising run of rows.
+    fn coverable<'s>(&self, rows: usize, shapes: impl Iterator<Item = &'s Shape>) -> bool {
+        let mut empty = [0; MAX_PC_ROWS];
+        for (row, empty) in empty.iter_mut().enumerate().take(rows) {
+            *empty = !(self.0 >> (row * WIDTH)) & FULL_ROW;
+        }
+        let mut covered = [0; MAX_PC_ROWS];
+
+        for shape in shapes {
+            for lines in 0..1u32 << rows {
+                if lines.count_ones() as usize != shape.height {
+                    continue;
+                }
+                let mut line = [0; 4];
+                let mut bits = lines;
+                for line in line.iter_mut().take(shape.height) {
+                    *line = bits.trailing_zeros() as usize;
+                    bits &= bits - 1;
+                }
+
+                // Columns the shape's left edge can take on these rows
+                let mut at = FULL_ROW >> (shape.width - 1);
+                for &(row, dx) in &shape.cells {
+                    at &= empty[line[row]] >> dx;
+                }
+                for &(row, dx) in &shape.cells {
+                    covered[line[row]] |= at << dx;
+                }
+            }
+
+            if covered == empty {
+                return true;
+            }
+        }
+        false
+    }
+
+    // Whether the empty cells split up between the pieces left, counted by
+    // kind in `pieces`, each lying the way `coverable` allows. Fields that
+    // split usually do so quickly, so after `limit` pieces tried this gives
+    // up and says they might.
+    fn tileable(
+        &self,
+        rows: usize,
+        pieces: &mut [usize; 7],
+        shapes: &[Vec<Shape>],
+        limit: &mut usize,
+    ) -> bool {
+        let empty = !self.0 & ((1 << (rows * WIDTH)) - 1);
+        if empty == 0 || *limit == 0 {
+            return true;
+        }
+        *limit -= 1;
+        let first = empty.trailing_zeros() as usize;
+        let (row, col) = (first / WIDTH, first % WIDTH);
+
+        for kind in 0..pieces.len() {
+            if pieces[kind] == 0 {
+                continue;
+            }
+            pieces[kind] -= 1;
+
+            for shape in &shapes[kind] {
+                // The piece's lowest, leftmost cell is the one that goes here
+                let Some(x) = col.checked_sub(shape.cells[0].1) else {
+                    continue;
+                };
+                if x + shape.width > WIDTH {
+                    continue;
+                }
+
+                for lines in 0..1u32 << (rows - row - 1) {
+                    if lines.count_ones() as usize + 1 != shape.height {
+                        continue;
+                    }
+                    let mut line = [row; 4];
+                    let mut rest = lines;
+                    for line in line.iter_mut().take(shape.height).skip(1) {
+                        *line = row + 1 + rest.trailing_zeros() as usize;
+                        rest &= rest - 1;
+                    }
+
+                    let bits = shape
+                        .cells
+                        .iter()
+                        .fold(0, |bits, &(r, dx)| bits | 1 << (line[r] * WIDTH + x + dx));
+                    if bits & self.0 == 0
+                        && Field(self.0 | bits).tileable(rows, pieces, shapes, limit)
+                    {
+                        pieces[kind] += 1;
+                        return true;
+                    }
+                }
+            }
+            pieces[kind] += 1;
+        }
+        false
+    }
+
+    // Takes out full rows below `rows`, dropping the ones above, and says
+    // how many went
+    fn clear_lines(&mut self, rows: usize) -> usize {
+        let mut cleared = 0;
+        for row in (0..rows).rev() {
+            let shift = row * WIDTH;
+            if (self.0 >> shift) & FULL_ROW == FULL_ROW {
+                let below = self.0 & ((1 << shift) - 1);
+                let above = (self.0 >> (shift + WIDTH)) << shift;
+                self.0 = below | above;
+                cleared += 1;
+            }
+        }
+        cleared
+    }
+}
+
+// One way a piece can lie, as (row, column) of each cell counted from its
+// bottom left
+#[derive(PartialEq)]
+struct Shape {
+    cells: [(usize, usize); 4],
+    height: usize,
+    width: usize,
+}
+
+// Each different way `kind` can lie
+fn shapes(kind: TetrominoType) -> Vec<Shape> {
+    let mut shapes: Vec<Shape> = Vec::new();
+    for orientation in 0..4 {
+        let mino = Tetromino {
+            orientation,
+            ..Tetromino::new(kind)
+        };
+        let cells = mino.cells();
+        let left = cells.iter().map(|&(x, _)| x).min().unwrap_or(0);
+        let right = cells.iter().map(|&(x, _)| x).max().unwrap_or(0);
+        let top = cells.iter().map(|&(_, y)| y).min().unwrap_or(0);
+        let bottom = cells.iter().map(|&(_, y)| y).max().unwrap_or(0);
+
+        let mut shape = Shape {
+            cells: [(0, 0); 4],
+            height: bottom - top + 1,
+            width: right - left + 1,
+        };
+        for (cell, &(x, y)) in shape.cells.iter_mut().zip(&cells) {
+            *cell = (bottom - y, x - left);
+        }
+        shape.cells.sort();
+        if !shapes.contains(&shape) {
+            shapes.push(shape);
+        }
+    }
+    shapes
+}
+
+// Cells a piece covers, sorted and counted up from the floor, and the same
+// cells as field bits
+type Cells = ([(usize, usize); 4], u64);
+
+// Spots a piece can sit at in the pit, one bit each. The pit is the clear
+// with HEADROOM empty rows over it, counted top down like a board, and the
+// piece at (x, y) is bit (y + 3) * 16 + x + 3, leaving room for pieces whose
+// box hangs off the left or the top.
+#[derive(Copy, Clone, Default, PartialEq, Eq)]
+struct Spots(u128, u128);
+
+impl Spots {
+    fn at(x: i32, y: i32) -> Spots {
+        Spots(1, 0).shift((y + 3) * 16 + x + 3)
+    }
+
+    // Every spot moved `by` bits higher, or lower when negative. Spots moved
+    // off either end are lost.
+    fn shift(self, by: i32) -> Spots {
+        let Spots(low, high) = self;
+        match by {
+            0 => self,
+            128.. => Spots(0, low << (by - 128)),
+            1..=127 => Spots(low << by, high << by | low >> (128 - by)),
+            -127..=-1 => Spots(low >> -by | high << (128 + by), high >> -by),
+            _ => Spots(high >> (-by - 128), 0),
+        }
+    }
+
+    fn iter(self) -> impl Iterator<Item = (i32, i32)> {
+        [(self.0, 0), (self.1, 128)]
+            .into_iter()
+            .flat_map(|(mut bits, base)| {
+                std::iter::from_fn(move || {
+                    (bits != 0).then(|| {
+                        let bit = bits.trailing_zeros() as i32;
+                        bits &= bits - 1;
+                        base + bit
+                    })
+                })
+            })
+            .map(|bit| (bit % 16 - 3, bit / 16 - 3))
+    }
+}
+
+impl BitAnd for Spots {
+    type Output = Spots;
+
+    fn bitand(self, other: Spots) -> Spots {
+        Spots(self.0 & other.0, self.1 & other.1)
+    }
+}
+
+impl BitOr for Spots {
+    type Output = Spots;
+
+    fn bitor(self, other: Spots) -> Spots {
+        Spots(self.0 | other.0, self.1 | other.1)
+    }
+}
+
+impl Not for Spots {
+    type Output = Spots;
+
+    fn not(self) -> Spots {
+        Spots(!self.0, !self.1)
+    }
+}
+
+// Every spot `from` gets to by moving `by` bits at a time through `fits`,
+// `from` included
+fn fill(from: Spots, fits: Spots, by: i32) -> Spots {
+    let mut reached = from;
+    let mut open = fits;
+    for step in [by, by * 2, by * 4, by * 8] {
+        reached = reached | (open & reached.shift(step));
+        open = open & open.shift(step);
+    }
+    reached
+}
+
+// The field with room above the clear for pieces to come in and turn
+struct Pit {
+    field: Field,
+    rows: usize,
+}
+
+impl Pit {
+    // Every set of cells `kind` can lock into inside the clear, however it
+    // gets there. Moves are worked out for every spot at once, with the same
+    // slides, drops and kicks the game uses. The ones that leave no gap under
+    // the piece come first, as most clears are built that way.
+    fn placements(&self, kind: TetrominoType) -> Vec<Cells> {
+        // The empty cells, as the spots a single cell could sit at
+        let mut open = Spots::default();
+        for row in 0..self.rows + HEADROOM {
+            let filled = if row < self.rows {
+                (self.field.0 >> (row * WIDTH)) & FULL_ROW
+            } else {
+                0
+            };
+            let y = (self.rows + HEADROOM - 1 - row) as i32;
+            open = open | Spots(!filled as u128 & FULL_ROW as u128, 0).shift((y + 3) * 16 + 3);
+        }
+
+        let minos: Vec<Tetromino> = (0..4)
+            .map(|orientation| Tetromino {
+                orientation,
+                ..Tetromino::new(kind)
+            })
+            .collect();
+        let shapes: Vec<Vec<(usize, usize)>> = minos.iter().map(Tetromino::cells).collect();
+
+        // Where each orientation fits, and where it rests on something
+        let fits: Vec<Spots> = shapes
+            .iter()
+            .map(|shape| {
+                shape.iter().fold(!Spots::default(), |fits, &(x, y)| {
+                    fits & open.shift(-(y as i32 * 16 + x as i32))
+                })
+            })
+            .collect();
+        let resting: Vec<Spots> = fits.iter().map(|&fits| !fits.shift(-16)).collect();
+
+        let mut reached = [Spots::default(); 4];
+        reached[0] = Spots::at(SPAWN.0, SPAWN.1);
+        loop {
+            let before = reached;
+            for (orientation, mino) in minos.iter().enumerate() {
+                let here = fits[orientation];
+                let from = reached[orientation];
+                let slid = fill(from, here, 1) | fill(from, here, -1);
+                // Soft drops go straight down as far as the piece goes
+                let dropped = fill(slid, here, 16) & resting[orientation];
+                reached[orientation] = slid | dropped;
+
+                // Each spot takes the first kick that fits
+                for dir in [
+                    RotationDirection::Clockwise,
+                    RotationDirection::CounterClockwise,
+                ] {
+                    let (rotated, kicks) = rotation(mino, dir);
+                    let to = rotated.orientation;
+                    let mut turning = reached[orientation];
+                    for &(kx, ky) in kicks {
+                        let by = ky as i32 * 16 + kx as i32;
+                        reached[to] = reached[to] | (turning.shift(by) & fits[to]);
+                        turning = turning & !fits[to].shift(-by);
+                    }
+                }
+            }
+            if reached == before {
+                break;
+            }
+        }
+
+        let bottom = (self.rows + HEADROOM - 1) as i32;
+        let mut placements: Vec<Cells> = Vec::new();
+        for (orientation, shape) in shapes.iter().enumerate() {
+            for (x, y) in (reached[orientation] & resting[orientation]).iter() {
+                let mut cells = [(0, 0); 4];
+                for (cell, &(cx, cy)) in cells.iter_mut().zip(shape) {
+                    *cell = ((x + cx as i32) as usize, (bottom - y - cy as i32) as usize);
+                }
+                // Sticking up out of the clear
+                if cells.iter().any(|&(_, row)| row >= self.rows) {
+                    continue;
+                }
+                cells.sort();
+                let bits = cells
+                    .iter()
+                    .fold(0, |bits, &(x, y)| bits | 1 << (y * WIDTH + x));
+                // Spins and plain drops into the same cells are the same here
+                if placements.iter().all(|&(_, seen)| seen != bits) {
+                    placements.push((cells, bits));
+                }
+            }
+        }
+
+        placements.sort_by_key(|&(_, bits)| {
+            let under = (bits >> WIDTH) & !bits;
+            under & !self.field.0 != 0
+        });
+        placements
+    }
+}
+
+// The search state, from which everything else follows: how many pieces are
+// down comes from the empty cells, so the field, rows left, hold and queue
+// position are the whole of it
+type State = (Field, usize, Option<TetrominoType>, usize);
+
+// The placements out of a state that lead to a clear, each with the state
+// it leaves
+type Edges = Vec<(Placement, State)>;
+
+struct Search<'a> {
+    queue: &'a [TetrominoType],
+    rules: Rules,
+    placements: Vec<Placement>,
+    // Every state searched so far with the ways on from it. States that lead
+    // nowhere have none. Reaching one again only takes replaying its ways
+    // on rather than searching it afresh.
+    searched: HashMap<State, Edges>,
+    // The ways each kind of piece can lie, by kind
+    shapes: Vec<Vec<Shape>>,
+}
+
+impl<'a> Search<'a> {
+    // The pieces that could go down next, with the hold and queue position
+    // that each leaves behind
+    fn choices(
+        &self,
+        hold: Option<TetrominoType>,
+        next: usize,
+    ) -> Vec<(TetrominoType, Option<TetrominoType>, usize)> {
+        let Some(&current) = self.queue.get(next) else {
+            // With the queue used up, holding brings out the held piece
+            return match hold {
+                Some(held) if self.rules.hold => vec![(held, None, next)],
+                _ => Vec::new(),
+            };
+        };
+
+        let mut choices = vec![(current, hold, next + 1)];
+        if !self.rules.hold {
+            return choices;
+        }
+
+        match hold {
+            Some(held) if held != current => choices.push((held, Some(current), next + 1)),
+            None => {
+                if let Some(&after) = self.queue.get(next + 1) {
+                    if after != current {
+                        choices.push((after, Some(current), next + 2));
+                    }
+                }
+            }
+            _ => (),
+        }
+        choices
+    }
+
+    fn allowed(&self, kind: TetrominoType) -> bool {
+        match self.rules.save {
+            Some((saved, after)) => kind != saved || self.placements.len() >= after,
+            None => true,
+        }
+    }
+
+    // Whether any solution goes through `state`, handing each one found to
+    // `found` as it turns up. An error from `found` ends the search.
+    fn search<E>(
+        &mut self,
+        state: State,
+        found: &mut impl FnMut(&[Placement]) -> Result<(), E>,
+    ) -> Result<bool, E> {
+        let (field, rows, hold, next) = state;
+        if rows == 0 {
+            found(&self.placements)?;
+            return Ok(true);
+        }
+
+        if let Some(edges) = self.searched.get(&state) {
+            if edges.is_empty() {
+                return Ok(false);
+            }
+            replay(&self.searched, state, &mut self.placements, found)?;
+            return Ok(true);
+        }
+
+        let empty = field.empty_cells(rows);
+        let available = self.queue.len().saturating_sub(next) + usize::from(hold.is_some());
+        if empty / 4 > available || !field.splittable(rows) {
+            return Ok(false);
+        }
+
+        // Fields the pieces left could never fill lead nowhere, however they
+        // go down
+        let mut pieces = [0; 7];
+        for &kind in self.queue[next.min(self.queue.len())..].iter().chain(&hold) {
+            pieces[kind as usize] += 1;
+        }
+        let shapes = TetrominoType::ALL
+            .into_iter()
+            .filter(|&kind| pieces[kind as usize] > 0)
+            .flat_map(|kind| &self.shapes[kind as usize]);
+        if !field.coverable(rows, shapes)
+            || !field.tileable(rows, &mut pieces, &self.shapes, &mut TILING_LIMIT.clone())
+        {
+            return Ok(false);
+        }
+
+        let mut edges = Vec::new();
+        for (kind, hold, next) in self.choices(hold, next) {
+            if !self.allowed(kind) {
+                continue;
+            }
+
+            for (cells, bits) in (Pit { field, rows }).placements(kind) {
+                let mut after = Field(field.0 | bits);
+                let cleared = after.clear_lines(rows);
+                let placement = Placement { kind, cells };
+                let child = (after, rows - cleared, hold, next);
+
+                self.placements.push(placement);
+                let solved = self.search(child, found);
+                self.placements.pop();
+                if solved? {
+                    edges.push((placement, child));
+                }
+            }
+        }
+
+        let solved = !edges.is_empty();
+        self.searched.insert(state, edges);
+        Ok(solved)
+    }
+}
+
+// Hands `found` every solution that goes on from `state`, a state already
+// searched, after the placements that led to it
+fn replay<E>(
+    searched: &HashMap<State, Edges>,
+    state: State,
+    placements: &mut Vec<Placement>,
+    found: &mut impl FnMut(&[Placement]) -> Result<(), E>,
+) -> Result<(), E> {
+    if state.1 == 0 {
+        return found(placements);
+    }
+
+    for &(placement, child) in &searched[&state] {
+        placements.push(placement);
+        let replayed = replay(searched, child, placements, found);
+        placements.pop();
+        replayed?;
+    }
+    Ok(())
+}
+
+// Hands `found` each distinct sequence of placements that clears the bottom
+// `rules.rows` rows of `position` with its pieces, using hold as needed, as
+// soon as it is found. Pieces get anywhere the game's own moves and kicks
+// take them, tucks and spins included, and the field has to be ten wide. An
+// error from `found` stops the search and is returned.
+pub fn solve_all<E>(
+    position: &Position,
+    rules: Rules,
+    mut found: impl FnMut(&[Placement]) -> Result<(), E>,
+) -> Result<(), E> {
+    let rows = rules.rows.min(MAX_PC_ROWS);
+    let Some(field) = Field::from_position(position, rows) else {
+        return Ok(());
+    };
+
+    let queue: Vec<TetrominoType> = position
+        .current
+        .into_iter()
+        .chain(position.queue.iter().copied())
+        .collect();
+
+    let mut search = Search {
+        queue: &queue,
+        rules,
+        placements: Vec::new(),
+        searched: HashMap::new(),
+        shapes: TetrominoType::ALL.into_iter().map(shapes).collect(),
+    };
+    search
+        .search((field, rows, position.hold, 0), &mut found)
+        .map(|_| ())
+}
+
+// The first solution `solve_all` comes to, if there is one
+pub fn solve(position: &Position, rules: Rules) -> Option<Vec<Placement>> {
+    let mut first = None;
+    let _ = solve_all(position, rules, |solution| {
+        first = Some(solution.to_vec());
+        Err(())
+    });
+    first
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::board::Stack;
+    use crate::movegen;
+
+    // The pit as a plain stack, for movegen to search
+    struct Rows(Vec<u16>);
+
+    impl Stack for Rows {
+        fn width(&self) -> usize {
+            WIDTH
+        }
+
+        fn rows(&self) -> &[u16] {
+            &self.0
+        }
+    }
+
+    fn position(field: &str, current: &str, queue: &str) -> Position {
+        let text = format!("current {}\nqueue {}\nfield\n{}", current, queue, field);
+        Position::from_text(&text).unwrap()
+    }
+
+    // Puts each placement down in turn on `field`, checking none overlaps
+    // what is already there, and says whether the clear is left empty
+    fn clears(field: &str, rows: usize, solution: &[Placement]) -> bool {
+        let position = position(field, "I", "-");
+        let mut field = Field::from_position(&position, rows).unwrap();
+        let mut rows = rows;
+
+        for placement in solution {
+            let bits = placement
+                .cells
+                .iter()
+                .fold(0, |bits, &(x, y)| bits | 1 << (y * WIDTH + x));
+            assert_eq!(field.0 & bits, 0, "{:?} overlaps the field", placement);
+            field.0 |= bits;
+            rows -= field.clear_lines(rows);
+        }
+        rows == 0 && field.0 == 0
+    }
+
+    #[test]
+    fn four_line_clear_from_empty() {
+        let empty = "..........\n";
+        let position = position(empty, "I", "LJOSZTIOL");
+        let solution = solve(&position, Rules::default()).unwrap();
+
+        assert_eq!(solution.len(), 10);
+        assert!(clears(empty, PC_ROWS, &solution));
+    }
+
+    #[test]
+    fn finds_every_first_bag_clear_once() {
+        let empty = "..........\n";
+        let rules = Rules {
+            hold: false,
+            ..Rules::default()
+        };
+        let mut solutions = Vec::new();
+        solve_all::<()>(&position(empty, "I", "LJOSZTIOL"), rules, |solution| {
+            solutions.push(solution.to_vec());
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(solutions.len(), 114);
+        assert!(solutions
+            .iter()
+            .all(|solution| clears(empty, PC_ROWS, solution)));
+        let distinct: HashSet<&Vec<Placement>> = solutions.iter().collect();
+        assert_eq!(distinct.len(), solutions.len());
+    }
+
+    #[test]
+    fn tucks_under_an_overhang() {
+        // The L only fits by sliding left under the top row once it is down
+        let field = "GGGGGGG...\nGGGGG.....\n";
+        let rules = Rules {
+            rows: 2,
+            hold: false,
+            save: None,
+        };
+        let solution = solve(&position(field, "L", "O"), rules).unwrap();
+
+        assert_eq!(
+            solution[0],
+            Placement {
+                kind: TetrominoType::L,
+                cells: [(5, 0), (6, 0), (7, 0), (7, 1)],
+            }
+        );
+        assert!(clears(field, 2, &solution));
+    }
+
+    #[test]
+    fn pit_reaches_what_movegen_reaches() {
+        let fields = [
+            "..........\n",
+            "GGGGGGG...\nGGGGG.....\n",
+            "GG........\nGGG...GGGG\nGGG....GGG\nGGGG.GGGGG\n",
+            "....GGGGGG\nG.....GGGG\nGG...GGG.G\nGGG.GGGG..\n",
+            // Some of these cells are only reached by kicks
+            "..GG.G..GG\n..GGG.G.GG\nGGGGG...G.\nG..G..GG..\n",
+            ".GGG..G..G\nG....G.G.G\n.GGGGGGGGG\n..G..G.GGG\n",
+        ];
+        for text in fields {
+            let field = Field::from_position(&position(text, "I", "-"), PC_ROWS).unwrap();
+            let mut rows = vec![0; HEADROOM + PC_ROWS];
+            for row in 0..PC_ROWS {
+                rows[HEADROOM + PC_ROWS - 1 - row] = ((field.0 >> (row * WIDTH)) & FULL_ROW) as u16;
+            }
+
+            for kind in TetrominoType::ALL {
+                let mut expected: Vec<[(usize, usize); 4]> = movegen::placements(
+                    &Rows(rows.clone()),
+                    &Tetromino::new(kind),
+                    SPAWN,
+                    &movegen::ALL_MOVES,
+                )
+                .iter()
+                .map(|placement| {
+                    let mut cells = [(0, 0); 4];
+                    for (cell, (x, y)) in cells.iter_mut().zip(placement.cells()) {
+                        *cell = (x as usize, rows.len() - 1 - y as usize);
+                    }
+                    cells.sort();
+                    cells
+                })
+                .filter(|cells| cells.iter().all(|&(_, row)| row < PC_ROWS))
+                .collect();
+                expected.sort();
+                expected.dedup();
+
+                let mut got: Vec<[(usize, usize); 4]> = Pit {
+                    field,
+                    rows: PC_ROWS,
+                }
+                .placements(kind)
+                .into_iter()
+                .map(|(cells, _)| cells)
+                .collect();
+                got.sort();
+
+                assert_eq!(got, expected, "{:?} on\n{}", kind, text);
+            }
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use crate::board::Status;
+use crate::fumen::{self, Page};
+use crate::mino::TetrominoType;
+
+// A shareable snapshot of a game: the locked cells and the pieces still to
+// come. Field rows run top to bottom and sit on the floor of the board, with
+// empty rows above the stack left out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Position {
+    pub field: Vec<Vec<Status>>,
+    pub current: Option<TetrominoType>,
+    pub hold: Option<TetrominoType>,
+    pub queue: Vec<TetrominoType>,
+}
+
+fn cell_char(cell: Status) -> char {
+    match cell {
+        Status::FillType(mino) => mino.letter(),
+        Status::Garbage => 'G',
+        Status::Empty | Status::FillGhost(_) | Status::Overlay(_) | Status::Hint(_) => '.',
+    }
+}
+
+fn parse_cell(letter: char) -> Result<Status, String> {
+    match letter {
+        '.' => Ok(Status::Empty),
+        'G' | 'g' => Ok(Status::Garbage),
+        other => TetrominoType::from_letter(other)
+            .map(Status::FillType)
+            .ok_or_else(|| format!("unknown cell '{}'", other)),
+    }
+}
+
+fn piece_name(mino: Option<TetrominoType>) -> String {
+    mino.map_or("-".to_string(), |mino| mino.letter().to_string())
+}
+
+fn parse_piece(text: &str) -> Result<Option<TetrominoType>, String> {
+    match text {
+        "-" | "" => Ok(None),
+        _ => {
+            let mut letters = text.chars();
+            match (
+                letters.next().and_then(TetrominoType::from_letter),
+                letters.next(),
+            ) {
+                (Some(mino), None) => Ok(Some(mino)),
+                _ => Err(format!("unknown piece '{}'", text)),
+            }
+        }
+    }
+}
+
+// A row the way the text format writes it, which parse_row reads back
+pub fn row_text(row: &[Status]) -> String {
+    row.iter().map(|cell| cell_char(*cell)).collect()
+}
+
+pub fn parse_row(line: &str) -> Result<Vec<Status>, String> {
+    line.chars().map(parse_cell).collect()
+}
+
+pub fn parse_queue(text: &str) -> Result<Vec<TetrominoType>, String> {
+    text.chars()
+        .map(|letter| {
+            TetrominoType::from_letter(letter).ok_or_else(|| format!("unknown piece '{}'", letter))
+        })
+        .collect()
+}
+
+impl Position {
+    pub fn new(width: usize) -> Self {
+        Position {
+            field: vec![vec![Status::Empty; width]],
+            current: None,
+            hold: None,
+            queue: Vec::new(),
+        }
+    }
+
+    // Drops empty rows off the top, keeping at least one so the width is
+    // still known.
+    pub fn trim(&mut self) {
+        let first = self
+            .field
+            .iter()
+            .position(|row| row.iter().any(|cell| cell_char(*cell) != '.'))
+            .unwrap_or(self.field.len().saturating_sub(1));
+        self.field.drain(..first);
+    }
+
+    // The text format looks like
+    //
+    //     hold T
+    //     current I
+    //     queue OSZJL
+    //     field
+    //     ....LL....
+    //     GGGG.GGGGG
+    //
+    // with `-` for no piece, `.` for an empty cell, a piece letter for a
+    // piece's cell and G for garbage.
+    pub fn to_text(&self) -> String {
+        let queue: String = self.queue.iter().map(|mino| mino.letter()).collect();
+
+        let mut text = format!(
+            "hold {}\ncurrent {}\nqueue {}\nfield\n",
+            piece_name(self.hold),
+            piece_name(self.current),
+            if queue.is_empty() { "-" } else { &queue }
+        );
+
+        for row in &self.field {
+            text.push_str(&row_text(row));
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut position = Position {
+            field: Vec::new(),
+            current: None,
+            hold: None,
+            queue: Vec::new(),
+        };
+        let mut in_field = false;
+
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            if in_field {
+                let row = parse_row(line)?;
+
+                if let Some(first) = position.field.first() {
+                    if first.len() != row.len() {
+                        return Err("field rows differ in width".to_string());
+                    }
+                }
+                position.field.push(row);
+                continue;
+            }
+
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+
+            match key {
+                "hold" => position.hold = parse_piece(value)?,
+                "current" => position.current = parse_piece(value)?,
+                "queue" if value == "-" => position.queue.clear(),
+                "queue" => position.queue = parse_queue(value)?,
+                "field" => in_field = true,
+                other => return Err(format!("unknown line '{}'", other)),
+            }
+        }
+
+        if position.field.is_empty() {
+            return Err("position has no field".to_string());
+        }
+        Ok(position)
+    }
+}
+
+pub fn exports_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("tetrs").join("exports.txt"))
+}
+
+// Appends the position as both fumen and text to the exports file, so a copy
+// survives even when the terminal has no clipboard support. Returns the fumen.
+pub fn export(position: &Position) -> io::Result<String> {
+    let fumen = fumen::encode(&[Page::from_position(position)]);
+
+    let path = exports_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory"))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}\n{}", fumen, position.to_text())?;
+    Ok(fumen)
+}
@@ -0,0 +1,321 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::board::{Board, Status};
+use crate::mode::{GameEnd, History, ModeController};
+use crate::position::{self, Position};
+use crate::stats::TSpin;
+
+// A few puzzles that are always there, one for each kind of goal
+const BUILTIN: [&str; 4] = [
+    "name Tetris
+goal lines 4
+current I
+queue -
+field
+GGGGGGGGG.
+GGGGGGGGG.
+GGGGGGGGG.
+GGGGGGGGG.
+",
+    "name T-spin double
+goal tsd
+current T
+queue -
+field
+GGG.......
+GG...GGGGG
+GGG.GGGGGG
+",
+    "name Two L perfect clear
+goal pc
+current L
+queue L
+field
+GGGGGG....
+GGGGGG....
+",
+    "name Fill the well
+goal field
+target
+GGGGGGOO..
+GGGGGGOO..
+current O
+queue O
+field
+GGGGGG....
+GGGGGG....
+",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Goal {
+    Lines(usize),
+    PerfectClear,
+    TSpinDouble,
+    // Cells compare by whether they are filled, not by colour
+    Field(Vec<Vec<Status>>),
+}
+
+impl Goal {
+    fn parse(text: &str) -> Result<Self, String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+
+        match words.as_slice() {
+            ["lines", lines] => lines
+                .parse()
+                .ok()
+                .filter(|lines| *lines > 0)
+                .map(Goal::Lines)
+                .ok_or_else(|| format!("bad line count '{}'", lines)),
+            ["pc"] => Ok(Goal::PerfectClear),
+            ["tsd"] => Ok(Goal::TSpinDouble),
+            ["field"] => Ok(Goal::Field(Vec::new())),
+            _ => Err(format!("unknown goal '{}'", text)),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Goal::Lines(1) => "Clear a line".to_string(),
+            Goal::Lines(lines) => format!("Clear {} lines", lines),
+            Goal::PerfectClear => "Perfect clear".to_string(),
+            Goal::TSpinDouble => "T-spin double".to_string(),
+            Goal::Field(_) => "Build the target".to_string(),
+        }
+    }
+}
+
+// A starting position, a fixed set of pieces and a goal to reach with them.
+//
+// Puzzle files are the position text format with a name and goal on top:
+//
+//     name T-spin double
+//     goal tsd
+//     current T
+//     queue -
+//     field
+//     ...
+//
+// where the goal is one of `lines N`, `pc`, `tsd` or `field`. A `field` goal
+// is followed by a `target` line and the rows the stack should end up as.
+#[derive(Clone, Debug)]
+pub struct Puzzle {
+    pub name: String,
+    pub goal: Goal,
+    pub start: Position,
+}
+
+fn is_filled(cell: &Status) -> bool {
+    matches!(cell, Status::FillType(_) | Status::Garbage)
+}
+
+fn same_shape(a: &[Vec<Status>], b: &[Vec<Status>]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).all(|(a, b)| {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| is_filled(a) == is_filled(b))
+        })
+}
+
+impl Puzzle {
+    pub fn from_text(text: &str, default_name: &str) -> Result<Self, String> {
+        let mut name = default_name.to_string();
+        let mut goal = None;
+        let mut target = Vec::new();
+        let mut in_target = false;
+        let mut rest = String::new();
+
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            if in_target {
+                if let Ok(row) = position::parse_row(line) {
+                    target.push(row);
+                    continue;
+                }
+                in_target = false;
+            }
+
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "name" => name = value.trim().to_string(),
+                "goal" => goal = Some(Goal::parse(value)?),
+                "target" => in_target = true,
+                _ => {
+                    rest.push_str(line);
+                    rest.push('\n');
+                }
+            }
+        }
+
+        let start = Position::from_text(&rest)?;
+        if start.current.is_none() && start.queue.is_empty() {
+            return Err("puzzle has no pieces".to_string());
+        }
+
+        let goal = match goal.ok_or("puzzle has no goal")? {
+            Goal::Field(_) if target.is_empty() => return Err("field goal has no target".into()),
+            Goal::Field(_) => {
+                let mut target = Position {
+                    field: target,
+                    ..start.clone()
+                };
+                target.trim();
+                Goal::Field(target.field)
+            }
+            goal => goal,
+        };
+
+        Ok(Puzzle { name, goal, start })
+    }
+}
+
+pub fn puzzles_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("tetrs").join("puzzles"))
+}
+
+// Files straight in `dir`, plus one level of subdirectories so a drill pack
+// can be dropped in as a folder
+fn puzzle_files(dir: &Path, depth: usize) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut files = Vec::new();
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.is_dir() && depth > 0 {
+            files.extend(puzzle_files(&path, depth - 1));
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    files
+}
+
+// The built in puzzles followed by every file in the puzzles directory. A file
+// that fails to load is kept as an error naming it, so the menu can show why.
+pub fn load_all() -> Vec<Result<Puzzle, String>> {
+    let mut puzzles: Vec<_> = BUILTIN
+        .iter()
+        .map(|text| Puzzle::from_text(text, "Built in"))
+        .collect();
+
+    let Some(dir) = puzzles_dir() else {
+        return puzzles;
+    };
+
+    for path in puzzle_files(&dir, 1) {
+        let relative = path.strip_prefix(&dir).unwrap_or(&path).with_extension("");
+        let label = relative.display().to_string();
+
+        let puzzle = fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| Puzzle::from_text(&text, &label))
+            .map_err(|err| format!("{}: {}", label, err));
+        puzzles.push(puzzle);
+    }
+
+    puzzles
+}
+
+// Plays a puzzle: nothing falls, pieces lock on a hard drop and come only from
+// the puzzle's sequence. Placements can be undone and the whole attempt
+// restarted, both of which count against the grade.
+pub struct PuzzleMode {
+    puzzle: Puzzle,
+    history: History,
+    undos: usize,
+    retries: usize,
+    solved: bool,
+}
+
+impl PuzzleMode {
+    pub fn new(puzzle: Puzzle) -> Self {
+        PuzzleMode {
+            puzzle,
+            history: History::default(),
+            undos: 0,
+            retries: 0,
+            solved: false,
+        }
+    }
+
+    // Checked after each placement
+    fn reached(&self, board: &Board) -> bool {
+        let clear = board.stats.last_clear;
+
+        match &self.puzzle.goal {
+            Goal::Lines(lines) => board.lines_cleared >= *lines,
+            Goal::PerfectClear => clear.is_some_and(|clear| clear.perfect),
+            Goal::TSpinDouble => {
+                clear.is_some_and(|clear| clear.lines == 2 && clear.tspin == TSpin::Full)
+            }
+            Goal::Field(target) => same_shape(&board.position().field, target),
+        }
+    }
+
+    fn grade(&self, board: &Board) -> &'static str {
+        match (self.solved, self.undos + self.retries) {
+            (false, _) => "F",
+            (true, 0) if board.finesse.faults == 0 => "S",
+            (true, 0) => "A",
+            (true, _) => "B",
+        }
+    }
+}
+
+impl ModeController for PuzzleMode {
+    fn setup(&mut self, board: &mut Board) {
+        board.set_gravity(Duration::MAX);
+        board.auto_lock = false;
+        board.fixed_sequence = true;
+        board.load_position(&self.puzzle.start);
+    }
+
+    fn update(&mut self, board: &mut Board, _elapsed: Duration) -> Option<GameEnd> {
+        if self.history.record(board) && board.pieces_placed > 0 && self.reached(board) {
+            self.solved = true;
+            return Some(GameEnd::Finished);
+        }
+
+        if board.topped_out || board.out_of_pieces() {
+            return Some(GameEnd::Failed);
+        }
+
+        None
+    }
+
+    fn hud(&self, board: &Board, _elapsed: Duration) -> Vec<String> {
+        let left = board.upcoming().len() + usize::from(!board.out_of_pieces());
+
+        vec![
+            self.puzzle.name.clone(),
+            format!("GOAL:    {}", self.puzzle.goal.describe()),
+            format!("PIECES:  {}", left),
+            self.history.hud_line(),
+        ]
+    }
+
+    fn results(&self, board: &Board, _elapsed: Duration) -> Vec<String> {
+        vec![
+            format!("Undos    {:>10}", self.undos),
+            format!("Retries  {:>10}", self.retries),
+            format!("Grade    {:>10}", self.grade(board)),
+        ]
+    }
+
+    fn undo(&mut self, board: &mut Board) {
+        if self.history.undo(board) {
+            self.undos += 1;
+        }
+    }
+
+    fn redo(&mut self, board: &mut Board) {
+        self.history.redo(board);
+    }
+
+    fn retry(&mut self, board: &mut Board) {
+        self.history.rewind(board);
+        self.retries += 1;
+    }
+}
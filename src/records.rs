@@ -0,0 +1,352 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::mode::{GameMode, Ranking};
+
+// Bump this and add a step to `migrate` whenever the file layout changes.
+pub const RECORDS_VERSION: u64 = 1;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GameRecord {
+    pub mode: GameMode,
+    #[serde(default)]
+    pub settings_hash: u64,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default)]
+    pub score: usize,
+    #[serde(default)]
+    pub time_ms: u64,
+    #[serde(default)]
+    pub lines: usize,
+    #[serde(default)]
+    pub pieces: usize,
+    #[serde(default)]
+    pub pps: f64,
+    #[serde(default)]
+    pub attack: usize,
+    #[serde(default)]
+    pub keys: usize,
+    #[serde(default)]
+    pub finesse_graded: usize,
+    #[serde(default)]
+    pub finesse_faults: usize,
+    // Unix seconds
+    #[serde(default)]
+    pub date: u64,
+    // Whether the mode's goal was reached, rather than the stack topping out
+    #[serde(default)]
+    pub finished: bool,
+}
+
+impl GameRecord {
+    pub fn time(&self) -> Duration {
+        Duration::from_millis(self.time_ms)
+    }
+
+    // In races only completed runs count
+    fn ranked(&self) -> bool {
+        self.mode.ranking() != Ranking::FastestTime || self.finished
+    }
+
+    fn beats(&self, other: &GameRecord) -> bool {
+        match self.mode.ranking() {
+            Ranking::FastestTime => self.time_ms < other.time_ms,
+            Ranking::LongestTime => self.time_ms > other.time_ms,
+            Ranking::HighestScore => self.score > other.score,
+        }
+    }
+}
+
+pub fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Records {
+    pub version: u64,
+    pub games: Vec<GameRecord>,
+}
+
+impl Default for Records {
+    fn default() -> Self {
+        Records {
+            version: RECORDS_VERSION,
+            games: Vec::new(),
+        }
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Brings an older file up to the current layout. There is only one version
+// so far, so this just refuses files written by a newer build.
+fn migrate(data: Value) -> io::Result<Records> {
+    let version = data
+        .get("version")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| invalid("records file has no version".to_string()))?;
+
+    match version {
+        RECORDS_VERSION => serde_json::from_value(data).map_err(io::Error::from),
+        _ => Err(invalid(format!(
+            "records file version {} is not supported",
+            version
+        ))),
+    }
+}
+
+impl Records {
+    pub fn path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("tetrs").join("records.json"))
+    }
+
+    // Unlike settings, a broken records file is an error rather than a
+    // silent reset, so a bad read never ends up overwriting the history.
+    pub fn load() -> io::Result<Self> {
+        let path = match Self::path() {
+            Some(path) => path,
+            None => return Ok(Records::default()),
+        };
+
+        match fs::read_to_string(path) {
+            Ok(data) => migrate(serde_json::from_str(&data)?),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Records::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory"))?;
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        // Write then rename, so a crash mid-write leaves the old file intact
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(temp, path)
+    }
+
+    // Ranked games of a mode played with the same gameplay settings. Games
+    // with a different queue, level or goal are not a fair comparison.
+    fn comparable(&self, mode: GameMode, settings_hash: u64) -> impl Iterator<Item = &GameRecord> {
+        self.games.iter().filter(move |game| {
+            game.mode == mode && game.settings_hash == settings_hash && game.ranked()
+        })
+    }
+
+    pub fn best(&self, mode: GameMode, settings_hash: u64) -> Option<&GameRecord> {
+        self.comparable(mode, settings_hash)
+            .fold(None, |best, game| match best {
+                Some(best) if !game.beats(best) => Some(best),
+                _ => Some(game),
+            })
+    }
+
+    // Best ranked games for a mode and its settings, best first
+    pub fn leaderboard(
+        &self,
+        mode: GameMode,
+        settings_hash: u64,
+        limit: usize,
+    ) -> Vec<&GameRecord> {
+        let mut games: Vec<&GameRecord> = self.comparable(mode, settings_hash).collect();
+
+        games.sort_by(|a, b| match (a.beats(b), b.beats(a)) {
+            (true, _) => std::cmp::Ordering::Less,
+            (_, true) => std::cmp::Ordering::Greater,
+            _ => std::cmp::Ordering::Equal,
+        });
+        games.truncate(limit);
+        games
+    }
+
+    // Every game that set a new personal best when it was played, oldest first
+    pub fn pb_history(&self, mode: GameMode, settings_hash: u64) -> Vec<&GameRecord> {
+        let mut history: Vec<&GameRecord> = Vec::new();
+
+        for game in self.comparable(mode, settings_hash) {
+            if history.last().is_none_or(|best| game.beats(best)) {
+                history.push(game);
+            }
+        }
+        history
+    }
+
+    // Appends a finished game and returns whether it is a new personal best
+    // for its mode and settings.
+    pub fn add(&mut self, record: GameRecord) -> bool {
+        let personal_best = record.ranked()
+            && self
+                .best(record.mode, record.settings_hash)
+                .is_none_or(|best| record.beats(best));
+
+        self.games.push(record);
+        personal_best
+    }
+}
+
+// Loads, appends and saves in one go, so concurrent sessions lose as little
+// as possible.
+pub fn record_game(record: GameRecord) -> io::Result<bool> {
+    let mut records = Records::load()?;
+    let personal_best = records.add(record);
+    records.save()?;
+    Ok(personal_best)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(mode: GameMode, settings_hash: u64, time_ms: u64, score: usize) -> GameRecord {
+        GameRecord {
+            mode,
+            settings_hash,
+            seed: 0,
+            score,
+            time_ms,
+            lines: 40,
+            pieces: 100,
+            pps: 0.0,
+            attack: 0,
+            keys: 0,
+            finesse_graded: 0,
+            finesse_faults: 0,
+            date: 0,
+            finished: true,
+        }
+    }
+
+    fn records(games: Vec<GameRecord>) -> Records {
+        Records {
+            version: RECORDS_VERSION,
+            games,
+        }
+    }
+
+    #[test]
+    fn best_follows_the_mode_ranking() {
+        let records = records(vec![
+            game(GameMode::Sprint, 1, 60_000, 0),
+            game(GameMode::Sprint, 1, 45_000, 0),
+            game(GameMode::Sprint, 1, 50_000, 0),
+            game(GameMode::Ultra, 1, 120_000, 3000),
+            game(GameMode::Ultra, 1, 120_000, 9000),
+        ]);
+
+        assert_eq!(records.best(GameMode::Sprint, 1).unwrap().time_ms, 45_000);
+        assert_eq!(records.best(GameMode::Ultra, 1).unwrap().score, 9000);
+        assert!(records.best(GameMode::Marathon, 1).is_none());
+    }
+
+    #[test]
+    fn unfinished_races_are_not_ranked() {
+        let mut quick = game(GameMode::Sprint, 1, 10_000, 0);
+        quick.finished = false;
+        let records = records(vec![quick, game(GameMode::Sprint, 1, 60_000, 0)]);
+
+        assert_eq!(records.best(GameMode::Sprint, 1).unwrap().time_ms, 60_000);
+        assert_eq!(records.leaderboard(GameMode::Sprint, 1, 10).len(), 1);
+    }
+
+    #[test]
+    fn other_settings_are_not_compared() {
+        let mut records = records(vec![
+            game(GameMode::Sprint, 1, 30_000, 0),
+            game(GameMode::Sprint, 2, 90_000, 0),
+        ]);
+
+        assert_eq!(records.best(GameMode::Sprint, 2).unwrap().time_ms, 90_000);
+        assert_eq!(records.leaderboard(GameMode::Sprint, 2, 10).len(), 1);
+        assert_eq!(records.pb_history(GameMode::Sprint, 2).len(), 1);
+
+        // Slower than the best overall, but the best with its own settings
+        assert!(records.add(game(GameMode::Sprint, 2, 60_000, 0)));
+        assert!(!records.add(game(GameMode::Sprint, 1, 60_000, 0)));
+    }
+
+    #[test]
+    fn leaderboard_is_sorted_and_limited() {
+        let records = records(vec![
+            game(GameMode::Ultra, 1, 120_000, 2000),
+            game(GameMode::Ultra, 1, 120_000, 5000),
+            game(GameMode::Ultra, 1, 120_000, 1000),
+            game(GameMode::Ultra, 1, 120_000, 4000),
+        ]);
+
+        let scores: Vec<usize> = records
+            .leaderboard(GameMode::Ultra, 1, 3)
+            .iter()
+            .map(|game| game.score)
+            .collect();
+        assert_eq!(scores, vec![5000, 4000, 2000]);
+    }
+
+    #[test]
+    fn pb_history_keeps_each_improvement_in_order() {
+        let records = records(vec![
+            game(GameMode::Survival, 1, 30_000, 0),
+            game(GameMode::Survival, 1, 20_000, 0),
+            game(GameMode::Survival, 1, 50_000, 0),
+            game(GameMode::Survival, 1, 50_000, 0),
+            game(GameMode::Survival, 1, 80_000, 0),
+        ]);
+
+        let times: Vec<u64> = records
+            .pb_history(GameMode::Survival, 1)
+            .iter()
+            .map(|game| game.time_ms)
+            .collect();
+        assert_eq!(times, vec![30_000, 50_000, 80_000]);
+    }
+
+    #[test]
+    fn add_reports_personal_bests() {
+        let mut records = records(Vec::new());
+
+        assert!(records.add(game(GameMode::Sprint, 1, 60_000, 0)));
+        assert!(!records.add(game(GameMode::Sprint, 1, 70_000, 0)));
+        assert!(records.add(game(GameMode::Sprint, 1, 50_000, 0)));
+        assert_eq!(records.games.len(), 3);
+    }
+
+    #[test]
+    fn migrate_reads_the_current_version() {
+        let data = serde_json::json!({
+            "version": RECORDS_VERSION,
+            "games": [{ "mode": GameMode::Sprint, "time_ms": 42_000, "finished": true }],
+        });
+
+        let records = migrate(data).unwrap();
+        assert_eq!(records.games.len(), 1);
+        assert_eq!(records.games[0].time_ms, 42_000);
+        assert_eq!(records.games[0].settings_hash, 0);
+    }
+
+    #[test]
+    fn migrate_refuses_unknown_versions() {
+        let missing = serde_json::json!({ "games": [] });
+        let newer = serde_json::json!({ "version": RECORDS_VERSION + 1, "games": [] });
+
+        for data in [missing, newer] {
+            match migrate(data) {
+                Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidData),
+                Ok(_) => panic!("an unknown version was accepted"),
+            }
+        }
+    }
+}
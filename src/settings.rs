@@ -0,0 +1,276 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crossterm::event::{KeyCode, ModifierKeyCode};
+use serde::{Deserialize, Serialize};
+
+use crate::bot::BOT_PPS;
+use crate::constants::MAX_NEXT_QUEUE;
+use crate::dig::DIG_ROWS;
+use crate::input::InputEvent;
+use crate::mode::{GameMode, MARATHON_LEVELS, ULTRA_MINUTES};
+use crate::stats::StatEntry;
+use crate::trainer::Opener;
+use crate::util::fnv1a;
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Keybinds {
+    pub move_left: KeyCode,
+    pub move_right: KeyCode,
+    pub rotate_cw: KeyCode,
+    pub rotate_ccw: KeyCode,
+    pub soft_drop: KeyCode,
+    pub hard_drop: KeyCode,
+    pub hold: KeyCode,
+    pub pause: KeyCode,
+    pub quit: KeyCode,
+    pub undo: KeyCode,
+    pub redo: KeyCode,
+    pub retry: KeyCode,
+    pub copy_position: KeyCode,
+    pub hint: KeyCode,
+}
+
+impl Default for Keybinds {
+    fn default() -> Self {
+        Keybinds {
+            move_left: KeyCode::Left,
+            move_right: KeyCode::Right,
+            rotate_cw: KeyCode::Up,
+            rotate_ccw: KeyCode::Modifier(ModifierKeyCode::LeftControl),
+            soft_drop: KeyCode::Down,
+            hard_drop: KeyCode::Char(' '),
+            hold: KeyCode::Modifier(ModifierKeyCode::LeftShift),
+            pause: KeyCode::Char('p'),
+            quit: KeyCode::Esc,
+            undo: KeyCode::Char('z'),
+            redo: KeyCode::Char('y'),
+            retry: KeyCode::Char('r'),
+            copy_position: KeyCode::Char('c'),
+            hint: KeyCode::Char('h'),
+        }
+    }
+}
+
+impl Keybinds {
+    pub fn action(&self, code: KeyCode) -> Option<InputEvent> {
+        let binds = [
+            (self.move_left, InputEvent::MoveLeft),
+            (self.move_right, InputEvent::MoveRight),
+            (self.rotate_cw, InputEvent::RotateCW),
+            (self.rotate_ccw, InputEvent::RotateCCW),
+            (self.soft_drop, InputEvent::SoftDrop),
+            (self.hard_drop, InputEvent::HardDrop),
+            (self.hold, InputEvent::Hold),
+            (self.pause, InputEvent::Pause),
+            (self.quit, InputEvent::Quit),
+            (self.undo, InputEvent::Undo),
+            (self.redo, InputEvent::Redo),
+            (self.retry, InputEvent::Retry),
+            (self.copy_position, InputEvent::CopyPosition),
+            (self.hint, InputEvent::Hint),
+        ];
+
+        binds
+            .into_iter()
+            .find(|(key, _)| *key == code)
+            .map(|(_, event)| event)
+    }
+
+    pub fn get(&self, event: &InputEvent) -> KeyCode {
+        match event {
+            InputEvent::MoveLeft => self.move_left,
+            InputEvent::MoveRight => self.move_right,
+            InputEvent::RotateCW => self.rotate_cw,
+            InputEvent::RotateCCW => self.rotate_ccw,
+            InputEvent::MoveDown | InputEvent::SoftDrop => self.soft_drop,
+            InputEvent::HardDrop => self.hard_drop,
+            InputEvent::Hold => self.hold,
+            InputEvent::Pause => self.pause,
+            InputEvent::Quit => self.quit,
+            InputEvent::Undo => self.undo,
+            InputEvent::Redo => self.redo,
+            InputEvent::Retry => self.retry,
+            InputEvent::CopyPosition => self.copy_position,
+            InputEvent::Hint => self.hint,
+        }
+    }
+
+    // Binds `code` to `event`. A key can only do one thing, so whatever it
+    // was bound to before takes the key `event` had.
+    pub fn bind(&mut self, event: &InputEvent, code: KeyCode) {
+        let old = self.get(event);
+        if let Some(other) = self.action(code) {
+            *self.get_mut(&other) = old;
+        }
+        *self.get_mut(event) = code;
+    }
+
+    pub fn get_mut(&mut self, event: &InputEvent) -> &mut KeyCode {
+        match event {
+            InputEvent::MoveLeft => &mut self.move_left,
+            InputEvent::MoveRight => &mut self.move_right,
+            InputEvent::RotateCW => &mut self.rotate_cw,
+            InputEvent::RotateCCW => &mut self.rotate_ccw,
+            InputEvent::MoveDown | InputEvent::SoftDrop => &mut self.soft_drop,
+            InputEvent::HardDrop => &mut self.hard_drop,
+            InputEvent::Hold => &mut self.hold,
+            InputEvent::Pause => &mut self.pause,
+            InputEvent::Quit => &mut self.quit,
+            InputEvent::Undo => &mut self.undo,
+            InputEvent::Redo => &mut self.redo,
+            InputEvent::Retry => &mut self.retry,
+            InputEvent::CopyPosition => &mut self.copy_position,
+            InputEvent::Hint => &mut self.hint,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub keys: Keybinds,
+    pub next_queue: usize,
+    pub finesse_hints: bool,
+    pub hidden_stats: Vec<StatEntry>,
+    pub marathon_start_level: usize,
+    pub marathon_endless: bool,
+    pub ultra_minutes: u64,
+    pub dig_rows: usize,
+    pub dig_repeat_holes: bool,
+    pub dig_messiness: u8,
+    pub pc_opener: Opener,
+    pub pc_auto_restart: bool,
+    pub bot_pps: f64,
+    // A TBP bot given on the command line to play in place of the built in
+    // one, for this run only
+    #[serde(skip)]
+    pub external_bot: Option<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            keys: Keybinds::default(),
+            next_queue: 5,
+            finesse_hints: true,
+            hidden_stats: Vec::new(),
+            marathon_start_level: 1,
+            marathon_endless: false,
+            ultra_minutes: ULTRA_MINUTES[0],
+            dig_rows: DIG_ROWS[0],
+            dig_repeat_holes: false,
+            dig_messiness: 100,
+            pc_opener: Opener::FirstPc,
+            pc_auto_restart: false,
+            bot_pps: BOT_PPS[2],
+            external_bot: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn shows(&self, entry: StatEntry) -> bool {
+        !self.hidden_stats.contains(&entry)
+    }
+
+    pub fn toggle_stat(&mut self, entry: StatEntry) {
+        if self.shows(entry) {
+            self.hidden_stats.push(entry);
+        } else {
+            self.hidden_stats.retain(|hidden| *hidden != entry);
+        }
+    }
+
+    // Identifies the options that change how a game of `mode` plays, so
+    // records set under different rules can be told apart. Display options
+    // and other modes' options are left out.
+    pub fn gameplay_hash(&self, mode: GameMode) -> u64 {
+        let mut gameplay = serde_json::json!({
+            "next_queue": self.next_queue,
+        });
+
+        match mode {
+            GameMode::Marathon => {
+                gameplay["start_level"] = self.marathon_start_level.into();
+                gameplay["endless"] = self.marathon_endless.into();
+            }
+            GameMode::Ultra => gameplay["minutes"] = self.ultra_minutes.into(),
+            GameMode::Dig => {
+                gameplay["rows"] = self.dig_rows.into();
+                gameplay["repeat_holes"] = self.dig_repeat_holes.into();
+                gameplay["messiness"] = self.dig_messiness.into();
+            }
+            _ => (),
+        }
+
+        fnv1a(gameplay.to_string().as_bytes())
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("tetrs").join("settings.json"))
+    }
+
+    // A missing or unreadable file just means defaults, the game should
+    // never refuse to start over its settings.
+    pub fn load() -> Self {
+        Self::path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|data| serde_json::from_str::<Settings>(&data).ok())
+            .map(Settings::checked)
+            .unwrap_or_default()
+    }
+
+    // Settings from a file of someone's choosing, where unlike the game's own
+    // file a mistake is worth hearing about
+    pub fn load_from(path: &Path) -> io::Result<Self> {
+        let data = fs::read_to_string(path)?;
+        let settings = serde_json::from_str::<Settings>(&data)?;
+        Ok(settings.checked())
+    }
+
+    // Anything out of range back to what the menus allow
+    fn checked(mut self) -> Self {
+        self.next_queue = self.next_queue.min(MAX_NEXT_QUEUE);
+        self.marathon_start_level = self.marathon_start_level.clamp(1, MARATHON_LEVELS);
+        if !ULTRA_MINUTES.contains(&self.ultra_minutes) {
+            self.ultra_minutes = ULTRA_MINUTES[0];
+        }
+        if !DIG_ROWS.contains(&self.dig_rows) {
+            self.dig_rows = DIG_ROWS[0];
+        }
+        self.dig_messiness = self.dig_messiness.min(100);
+        if !BOT_PPS.contains(&self.bot_pps) {
+            self.bot_pps = BOT_PPS[2];
+        }
+        self
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let data = serde_json::to_string_pretty(self)?;
+        fs::write(path, data)
+    }
+}
+
+pub fn key_name(code: KeyCode) -> String {
+    match code {
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(c) => c.to_uppercase().to_string(),
+        KeyCode::Modifier(ModifierKeyCode::LeftControl) => "LCtrl".to_string(),
+        KeyCode::Modifier(ModifierKeyCode::RightControl) => "RCtrl".to_string(),
+        KeyCode::Modifier(ModifierKeyCode::LeftShift) => "LShift".to_string(),
+        KeyCode::Modifier(ModifierKeyCode::RightShift) => "RShift".to_string(),
+        KeyCode::Modifier(ModifierKeyCode::LeftAlt) => "LAlt".to_string(),
+        KeyCode::Modifier(ModifierKeyCode::RightAlt) => "RAlt".to_string(),
+        other => format!("{:?}", other),
+    }
+}
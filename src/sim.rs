@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::board::TopOut;
+use crate::bot::{self, Bot};
+use crate::mode::{self, GameEnd, GameMode};
+use crate::settings::Settings;
+
+// Modes a bot can play on its own, with no opponent or puzzle to set up
+pub const MODES: [GameMode; 6] = [
+    GameMode::Marathon,
+    GameMode::Sprint,
+    GameMode::Ultra,
+    GameMode::Zen,
+    GameMode::Dig,
+    GameMode::Survival,
+];
+
+// Where games that never end by themselves, like Zen, are cut off
+pub const MAX_PIECES: usize = 10_000;
+
+// How one simulated game went
+#[derive(Clone, Debug, Serialize)]
+pub struct SimResult {
+    pub seed: u64,
+    pub mode: GameMode,
+    // None when the game reached the piece limit first
+    pub end: Option<GameEnd>,
+    pub top_out: Option<TopOut>,
+    pub pieces: usize,
+    pub lines: usize,
+    pub score: usize,
+    pub attack: usize,
+    // Game time, with each piece taking the same share of a second
+    pub time_ms: u64,
+}
+
+// Plays a game of `mode` dealt from `seed` with `bot` and no terminal. Time
+// only passes as pieces go down, a piece every 1/pps seconds, so gravity and
+// the lock delay never get a say, but timed modes and rising garbage keep to
+// the same clock. Fails when an external bot does.
+pub fn run(
+    mode: GameMode,
+    settings: &Settings,
+    bot: &mut Bot,
+    seed: u64,
+    pps: f64,
+    max_pieces: usize,
+) -> Result<SimResult, String> {
+    let mut controller = mode::controller(mode, settings)
+        .ok_or_else(|| format!("{} cannot be simulated", mode.name()))?;
+    let mut board = mode::start_board(controller.as_mut(), seed, None);
+
+    let interval = Duration::from_secs_f64(1.0 / pps);
+    let mut elapsed = Duration::ZERO;
+
+    let end = loop {
+        if let Some(end) = controller.update(&mut board, elapsed) {
+            break Some(end);
+        }
+        if board.pieces_placed >= max_pieces || board.active().is_none() {
+            break None;
+        }
+
+        match bot.decide(&board) {
+            Some(plan) => bot::play(&mut board, &plan),
+            None => {
+                if let Some(error) = bot.error() {
+                    return Err(format!("bot failed: {}", error));
+                }
+                // Nothing fits, so drop it where it is and let the game end
+                board.hard_drop();
+            }
+        }
+        elapsed += interval;
+    };
+
+    let elapsed = controller
+        .time_limit()
+        .map_or(elapsed, |limit| elapsed.min(limit));
+
+    Ok(SimResult {
+        seed,
+        mode,
+        end,
+        top_out: board.top_out,
+        pieces: board.pieces_placed,
+        lines: board.lines_cleared,
+        score: board.stats.score,
+        attack: board.stats.attack,
+        time_ms: elapsed.as_millis() as u64,
+    })
+}
@@ -0,0 +1,307 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::mino::TetrominoType;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TSpin {
+    None,
+    Mini,
+    Full,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct LineClear {
+    pub lines: usize,
+    pub tspin: TSpin,
+    pub perfect: bool,
+    // Whether this clear continued a back-to-back chain
+    pub b2b: bool,
+    // Consecutive clearing pieces before this one
+    pub combo: usize,
+}
+
+impl LineClear {
+    pub fn difficult(&self) -> bool {
+        self.lines == 4 || (self.lines > 0 && self.tspin != TSpin::None)
+    }
+}
+
+const COMBO_ATTACK: [usize; 12] = [0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5];
+const PERFECT_CLEAR_ATTACK: usize = 10;
+
+// Garbage lines sent for a clear, using the guideline attack table.
+pub fn attack(clear: &LineClear) -> usize {
+    if clear.lines == 0 {
+        return 0;
+    }
+
+    let base = match (clear.tspin, clear.lines) {
+        (TSpin::Full, lines) => lines * 2,
+        (TSpin::Mini, lines) => lines - 1,
+        (TSpin::None, 4) => 4,
+        (TSpin::None, lines) => lines - 1,
+    };
+
+    let b2b = if clear.b2b { 1 } else { 0 };
+    let combo = COMBO_ATTACK[clear.combo.min(COMBO_ATTACK.len() - 1)];
+    let perfect = if clear.perfect {
+        PERFECT_CLEAR_ATTACK
+    } else {
+        0
+    };
+
+    base + b2b + combo + perfect
+}
+
+// Points for a clear before the back-to-back bonus, from the guideline
+// scoring table.
+fn clear_points(clear: &LineClear) -> usize {
+    let base = match (clear.tspin, clear.lines) {
+        (TSpin::Full, lines) => 400 * (lines + 1),
+        (TSpin::Mini, lines) => 100 * (1 << lines),
+        (TSpin::None, 1) => 100,
+        (TSpin::None, 2) => 300,
+        (TSpin::None, 3) => 500,
+        (TSpin::None, 4) => 800,
+        (TSpin::None, _) => 0,
+    };
+
+    let perfect = match (clear.perfect, clear.lines) {
+        (false, _) => 0,
+        (true, 1) => 800,
+        (true, 2) => 1200,
+        (true, 3) => 1800,
+        (true, _) => 2000,
+    };
+
+    base + perfect
+}
+
+pub fn score(clear: &LineClear) -> usize {
+    let points = clear_points(clear);
+    let points = if clear.b2b { points * 3 / 2 } else { points };
+    points + 50 * clear.combo
+}
+
+fn piece_index(mino: TetrominoType) -> usize {
+    match mino {
+        TetrominoType::I => 0,
+        TetrominoType::O => 1,
+        TetrominoType::T => 2,
+        TetrominoType::S => 3,
+        TetrominoType::Z => 4,
+        TetrominoType::J => 5,
+        TetrominoType::L => 6,
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Stats {
+    pub keys: usize,
+    pub holds: usize,
+    pub attack: usize,
+    pub score: usize,
+    pub tetrises: usize,
+    pub tspins: usize,
+    pub combo: usize,
+    pub b2b: bool,
+    pub last_clear: Option<LineClear>,
+    pieces: [usize; 7],
+}
+
+impl Stats {
+    pub fn pieces_of(&self, mino: TetrominoType) -> usize {
+        self.pieces[piece_index(mino)]
+    }
+
+    pub fn total_pieces(&self) -> usize {
+        self.pieces.iter().sum()
+    }
+
+    pub fn record_piece(&mut self, mino: TetrominoType) {
+        self.pieces[piece_index(mino)] += 1;
+    }
+
+    // Fills in the chain state for a piece that just locked and returns the
+    // completed clear. Clear points are multiplied by the current level.
+    pub fn record_clear(
+        &mut self,
+        lines: usize,
+        tspin: TSpin,
+        perfect: bool,
+        level: usize,
+    ) -> LineClear {
+        let mut clear = LineClear {
+            lines,
+            tspin,
+            perfect,
+            b2b: false,
+            combo: 0,
+        };
+
+        if lines == 0 {
+            self.combo = 0;
+        } else {
+            clear.combo = self.combo;
+            clear.b2b = self.b2b && clear.difficult();
+
+            self.combo += 1;
+            self.b2b = clear.difficult();
+
+            if lines == 4 {
+                self.tetrises += 1;
+            }
+        }
+
+        if tspin != TSpin::None {
+            self.tspins += 1;
+        }
+
+        self.attack += attack(&clear);
+        self.score += score(&clear) * level;
+        self.last_clear = Some(clear);
+        clear
+    }
+
+    // One point per cell soft dropped, two per cell hard dropped
+    pub fn record_drop(&mut self, cells: usize, hard: bool) {
+        self.score += if hard { cells * 2 } else { cells };
+    }
+
+    pub fn keys_per_piece(&self) -> f64 {
+        ratio(self.keys, self.total_pieces())
+    }
+
+    // Share of cleared lines that came from tetrises
+    pub fn tetris_rate(&self, lines_cleared: usize) -> f64 {
+        ratio(self.tetrises * 4, lines_cleared) * 100.0
+    }
+
+    // Share of T pieces that were spun in
+    pub fn tspin_rate(&self) -> f64 {
+        ratio(self.tspins, self.pieces_of(TetrominoType::T)) * 100.0
+    }
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    if denominator == 0 {
+        return 0.0;
+    }
+    numerator as f64 / denominator as f64
+}
+
+pub fn per_second(count: usize, elapsed: Duration) -> f64 {
+    let seconds = elapsed.as_secs_f64();
+    if seconds <= 0.0 {
+        return 0.0;
+    }
+    count as f64 / seconds
+}
+
+pub fn per_minute(count: usize, elapsed: Duration) -> f64 {
+    let minutes = elapsed.as_secs_f64() / 60.0;
+    if minutes <= 0.0 {
+        return 0.0;
+    }
+    count as f64 / minutes
+}
+
+// Game time that stops while paused or suspended.
+pub struct Clock {
+    started: Instant,
+    excluded: Duration,
+    paused_at: Option<Instant>,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock {
+            started: Instant::now(),
+            excluded: Duration::ZERO,
+            paused_at: None,
+        }
+    }
+}
+
+impl Clock {
+    pub fn elapsed(&self) -> Duration {
+        let now = self.paused_at.unwrap_or_else(Instant::now);
+        now.duration_since(self.started)
+            .saturating_sub(self.excluded)
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    pub fn pause(&mut self) {
+        if self.paused_at.is_none() {
+            self.paused_at = Some(Instant::now());
+        }
+    }
+
+    pub fn resume(&mut self) {
+        if let Some(at) = self.paused_at.take() {
+            self.excluded += at.elapsed();
+        }
+    }
+
+    pub fn skip(&mut self, duration: Duration) {
+        self.excluded += duration;
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum StatEntry {
+    Score,
+    Fps,
+    Pps,
+    Lines,
+    Time,
+    Kpp,
+    Apm,
+    Lpm,
+    Finesse,
+    Pieces,
+    Holds,
+    TetrisRate,
+    TSpinRate,
+}
+
+impl StatEntry {
+    pub const ALL: [StatEntry; 13] = [
+        StatEntry::Score,
+        StatEntry::Fps,
+        StatEntry::Pps,
+        StatEntry::Lines,
+        StatEntry::Time,
+        StatEntry::Kpp,
+        StatEntry::Apm,
+        StatEntry::Lpm,
+        StatEntry::Finesse,
+        StatEntry::Pieces,
+        StatEntry::Holds,
+        StatEntry::TetrisRate,
+        StatEntry::TSpinRate,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StatEntry::Score => "Score",
+            StatEntry::Fps => "FPS",
+            StatEntry::Pps => "Pieces/sec",
+            StatEntry::Lines => "Lines",
+            StatEntry::Time => "Time",
+            StatEntry::Kpp => "Keys/piece",
+            StatEntry::Apm => "Attack/min",
+            StatEntry::Lpm => "Lines/min",
+            StatEntry::Finesse => "Finesse",
+            StatEntry::Pieces => "Piece counts",
+            StatEntry::Holds => "Holds",
+            StatEntry::TetrisRate => "Tetris rate",
+            StatEntry::TSpinRate => "T-spin rate",
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use crate::board::Board;
+use crate::garbage::HoleGenerator;
+use crate::mode::{GameEnd, ModeController};
+use crate::util::format_time;
+
+const FIRST_RISE: Duration = Duration::from_secs(5);
+const FASTEST_RISE: Duration = Duration::from_millis(750);
+// Each row comes this much sooner than the one before it
+const RISE_SPEEDUP: f64 = 0.96;
+// Survival garbage is mostly clean, with the odd switch to keep it honest
+const MESSINESS: u8 = 20;
+
+// Garbage rises from the bottom on a timer that keeps getting shorter, until
+// the stack tops out.
+pub struct Survival {
+    holes: HoleGenerator,
+    interval: Duration,
+    next_rise: Duration,
+    risen: usize,
+}
+
+impl Default for Survival {
+    fn default() -> Self {
+        Survival {
+            holes: HoleGenerator::new(0, true, MESSINESS),
+            interval: FIRST_RISE,
+            next_rise: FIRST_RISE,
+            risen: 0,
+        }
+    }
+}
+
+impl ModeController for Survival {
+    fn setup(&mut self, board: &mut Board) {
+        self.holes = HoleGenerator::new(board.seed(), true, MESSINESS);
+    }
+
+    fn update(&mut self, board: &mut Board, elapsed: Duration) -> Option<GameEnd> {
+        while elapsed >= self.next_rise && !board.topped_out {
+            board.add_garbage(&self.holes.holes(board.width, 1));
+            self.risen += 1;
+
+            self.interval = self.interval.mul_f64(RISE_SPEEDUP).max(FASTEST_RISE);
+            self.next_rise += self.interval;
+        }
+
+        if board.topped_out {
+            return Some(GameEnd::ToppedOut);
+        }
+
+        None
+    }
+
+    fn hud(&self, _board: &Board, elapsed: Duration) -> Vec<String> {
+        let next = self.next_rise.saturating_sub(elapsed);
+        vec![
+            format!("RISEN:   {}", self.risen),
+            format!("NEXT:    {:.1}s", next.as_secs_f64()),
+        ]
+    }
+
+    fn results(&self, _board: &Board, elapsed: Duration) -> Vec<String> {
+        vec![
+            format!("Survived {:>10}", format_time(elapsed)),
+            format!("Rows     {:>10}", self.risen),
+        ]
+    }
+}
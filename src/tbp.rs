@@ -0,0 +1,712 @@
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::board::{Board, Status};
+use crate::bot::Plan;
+use crate::mino::TetrominoType;
+use crate::movegen::{self, Placement};
+use crate::position::Position;
+use crate::stats::TSpin;
+
+// Rows in a TBP board, bottom first, however tall the field really is
+const BOARD_ROWS: usize = 40;
+// How long a bot gets to quit by itself before it is killed
+const QUIT_GRACE: Duration = Duration::from_millis(200);
+// How long a bot gets to answer before it counts as failed
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(5);
+
+const ORIENTATIONS: [&str; 4] = ["north", "east", "south", "west"];
+
+// Cells of each piece facing north around its centre, with y counting up.
+// The other orientations turn these clockwise about the centre.
+fn north_cells(mino: TetrominoType) -> [(i32, i32); 4] {
+    match mino {
+        TetrominoType::I => [(-1, 0), (0, 0), (1, 0), (2, 0)],
+        TetrominoType::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        TetrominoType::T => [(-1, 0), (0, 0), (1, 0), (0, 1)],
+        TetrominoType::L => [(-1, 0), (0, 0), (1, 0), (1, 1)],
+        TetrominoType::J => [(-1, 0), (0, 0), (1, 0), (-1, 1)],
+        TetrominoType::S => [(-1, 0), (0, 0), (0, 1), (1, 1)],
+        TetrominoType::Z => [(-1, 1), (0, 1), (0, 0), (1, 0)],
+    }
+}
+
+fn offsets(mino: TetrominoType, orientation: usize) -> [(i32, i32); 4] {
+    north_cells(mino).map(|(mut x, mut y)| {
+        for _ in 0..orientation {
+            (x, y) = (y, -x);
+        }
+        (x, y)
+    })
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Location {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub orientation: String,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Location {
+    // Board cells the piece covers, sorted like movegen::placement_cells
+    fn cells(&self, board: &Board) -> Option<Vec<(i32, i32)>> {
+        let mino = self
+            .kind
+            .chars()
+            .next()
+            .and_then(TetrominoType::from_letter)?;
+        let orientation = ORIENTATIONS.iter().position(|o| *o == self.orientation)?;
+
+        let bottom = board.height as i32 - 1;
+        let mut cells: Vec<(i32, i32)> = offsets(mino, orientation)
+            .iter()
+            .map(|&(dx, dy)| (self.x + dx, bottom - (self.y + dy)))
+            .collect();
+        cells.sort();
+        Some(cells)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TbpMove {
+    pub location: Location,
+    pub spin: String,
+}
+
+// Messages from the bot; anything newer than this version of the protocol
+// is ignored
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BotMessage {
+    Info {
+        name: String,
+        // Protocol extensions it understands
+        #[serde(default)]
+        features: Vec<String>,
+    },
+    Ready,
+    Error {
+        reason: String,
+    },
+    Suggestion {
+        moves: Vec<TbpMove>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+// What the randomizer extension adds to a start message
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Randomizer {
+    SevenBag { bag_state: Vec<String> },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum FrontendMessage {
+    Rules {
+        // Only for bots with the randomizer extension
+        #[serde(skip_serializing_if = "Option::is_none")]
+        randomizer: Option<&'static str>,
+    },
+    Start {
+        hold: Option<String>,
+        queue: Vec<String>,
+        combo: usize,
+        back_to_back: bool,
+        board: Vec<Vec<Option<String>>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        randomizer: Option<Randomizer>,
+    },
+    Suggest,
+    Play {
+        #[serde(rename = "move")]
+        mv: TbpMove,
+    },
+    NewPiece {
+        piece: String,
+    },
+    Stop,
+    Quit,
+}
+
+// How a placement scores, in the protocol's words. It is played out on a
+// copy so a far kick upgrades a mini the way it does in the game.
+fn spin_name(board: &Board, placement: &Placement) -> &'static str {
+    let mut after = board.clone();
+    crate::bot::play(
+        &mut after,
+        &Plan {
+            hold: false,
+            placement: placement.clone(),
+            score: 0.0,
+        },
+    );
+
+    match after.stats.last_clear.map(|clear| clear.tspin) {
+        Some(TSpin::Mini) => "mini",
+        Some(TSpin::Full) => "full",
+        _ => "none",
+    }
+}
+
+fn piece(mino: TetrominoType) -> String {
+    mino.letter().to_string()
+}
+
+fn tbp_board(position: &Position) -> Vec<Vec<Option<String>>> {
+    let width = position.field[0].len();
+    let mut rows: Vec<Vec<Option<String>>> = position
+        .field
+        .iter()
+        .rev()
+        .map(|row| {
+            row.iter()
+                .map(|cell| match cell {
+                    Status::FillType(mino) => Some(piece(*mino)),
+                    Status::Garbage => Some("G".to_string()),
+                    _ => None,
+                })
+                .collect()
+        })
+        .collect();
+    rows.resize(BOARD_ROWS, vec![None; width]);
+    rows
+}
+
+// Every piece dealt so far, wherever it is now
+fn dealt(board: &Board) -> usize {
+    board.pieces_placed
+        + usize::from(board.active().is_some())
+        + usize::from(board.held_piece().is_some())
+        + board.upcoming().len()
+}
+
+enum State {
+    // Waiting for the bot to introduce itself
+    Starting,
+    // Rules sent, waiting for it to accept them
+    Rules,
+    Ready,
+    Failed(String),
+}
+
+// An external bot speaking the Tetris Bot Protocol over its stdin and stdout.
+// It is told the position at the start and again whenever the board changes
+// in a way it could not have seen coming, like rising garbage, and is asked
+// for a move each piece.
+pub struct Tbp {
+    child: Option<Child>,
+    stdin: Option<ChildStdin>,
+    messages: Option<Receiver<BotMessage>>,
+    name: Option<String>,
+    state: State,
+    // Whether it was told pieces come from a seven bag
+    randomizer: bool,
+    // The bot's picture of the game after the last move it was told about
+    expected: Option<Position>,
+    started: bool,
+    suggesting: bool,
+    // When the bot was asked something it has yet to answer
+    asked: Option<Instant>,
+}
+
+impl Tbp {
+    // Launches the bot at `path`. A bot that cannot be started still makes a
+    // Tbp, one that has already failed and says why.
+    pub fn spawn(path: &Path) -> Self {
+        let mut tbp = Tbp {
+            child: None,
+            stdin: None,
+            messages: None,
+            name: None,
+            state: State::Starting,
+            randomizer: false,
+            expected: None,
+            started: false,
+            suggesting: false,
+            asked: None,
+        };
+
+        let spawned = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            // Anything it logs would land on top of the game
+            .stderr(Stdio::null())
+            .spawn();
+
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(err) => {
+                tbp.state = State::Failed(err.to_string());
+                return tbp;
+            }
+        };
+
+        let (tx, rx) = mpsc::channel();
+        let stdout = child.stdout.take().unwrap();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if let Ok(message) = serde_json::from_str(&line) {
+                    if tx.send(message).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        tbp.stdin = child.stdin.take();
+        tbp.child = Some(child);
+        tbp.messages = Some(rx);
+        // It owes us its info
+        tbp.asked = Some(Instant::now());
+        tbp
+    }
+
+    // The bot's own name once it has sent it, or what went wrong
+    pub fn name(&self) -> String {
+        match (self.error(), &self.name) {
+            (Some(reason), _) => format!("error: {}", reason),
+            (None, Some(name)) => name.clone(),
+            (None, None) => "starting".to_string(),
+        }
+    }
+
+    fn send(&mut self, message: &FrontendMessage) {
+        let Some(stdin) = &mut self.stdin else {
+            return;
+        };
+
+        let line = serde_json::to_string(message).unwrap();
+        if writeln!(stdin, "{}", line)
+            .and_then(|_| stdin.flush())
+            .is_err()
+        {
+            self.state = State::Failed("bot closed its input".to_string());
+        }
+    }
+
+    fn receive(&mut self) -> Option<BotMessage> {
+        match self.messages.as_ref()?.try_recv() {
+            Ok(message) => Some(message),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.state = State::Failed("bot exited".to_string());
+                None
+            }
+        }
+    }
+
+    fn start(&mut self, board: &Board) {
+        if self.started {
+            self.send(&FrontendMessage::Stop);
+        }
+        self.started = true;
+
+        // The board deals whole bags onto the end of its queue, and the whole
+        // queue is sent, so the piece after it always opens a fresh bag
+        let randomizer = self.randomizer.then(|| Randomizer::SevenBag {
+            bag_state: TetrominoType::ALL.into_iter().map(piece).collect(),
+        });
+
+        let position = board.position();
+        self.send(&FrontendMessage::Start {
+            hold: position.hold.map(piece),
+            queue: position
+                .current
+                .into_iter()
+                .chain(position.queue.iter().copied())
+                .map(piece)
+                .collect(),
+            combo: board.stats.combo,
+            back_to_back: board.stats.b2b,
+            board: tbp_board(&position),
+            randomizer,
+        });
+        self.expected = Some(position);
+    }
+
+    // Turns a suggested move into keys for `board`, holding first if the
+    // move is for the piece a hold would bring out. The piece has to land
+    // with the spin the bot asked for, so a T-spin is not played flat.
+    fn plan_for(board: &Board, mv: &TbpMove) -> Option<Plan> {
+        let active = board.active()?.tr_type;
+        let kind = mv.location.kind.chars().next()?;
+
+        let mut from = board.clone();
+        let hold = active.letter() != kind;
+        if hold {
+            from.hold_piece();
+            if !from.hold_used() || from.active()?.tr_type.letter() != kind {
+                return None;
+            }
+        }
+
+        let target = mv.location.cells(board)?;
+        let placement: Placement =
+            movegen::active_placements(&from)
+                .into_iter()
+                .find(|placement| {
+                    placement.cells() == target && spin_name(&from, placement) == mv.spin
+                })?;
+
+        Some(Plan {
+            hold,
+            placement,
+            score: 0.0,
+        })
+    }
+
+    // Handles what the bot has sent and asks it for moves when it is free.
+    // Returns its suggestions once they arrive.
+    fn poll(&mut self, board: &Board) -> Option<Vec<TbpMove>> {
+        while let Some(message) = self.receive() {
+            match message {
+                BotMessage::Info { name, features } if matches!(self.state, State::Starting) => {
+                    self.name = Some(name);
+                    // Pieces always come from a seven bag, but only bots that
+                    // say they know about randomizers are told so
+                    self.randomizer = features.iter().any(|feature| feature == "randomizer");
+                    self.send(&FrontendMessage::Rules {
+                        randomizer: self.randomizer.then_some("seven_bag"),
+                    });
+                    self.state = State::Rules;
+                    self.asked = Some(Instant::now());
+                }
+                BotMessage::Ready if matches!(self.state, State::Rules) => {
+                    self.state = State::Ready;
+                    self.asked = None;
+                }
+                BotMessage::Error { reason } => self.state = State::Failed(reason),
+                BotMessage::Suggestion { moves } if self.suggesting => {
+                    self.suggesting = false;
+                    self.asked = None;
+                    return Some(moves);
+                }
+                _ => (),
+            }
+        }
+
+        let late = self
+            .asked
+            .is_some_and(|asked| asked.elapsed() > ANSWER_TIMEOUT);
+        if late && !self.failed() {
+            self.state = State::Failed(format!("no answer within {}s", ANSWER_TIMEOUT.as_secs()));
+        }
+        if !matches!(self.state, State::Ready) || self.suggesting {
+            return None;
+        }
+
+        let in_sync = self.expected.as_ref().is_some_and(|expected| {
+            let position = board.position();
+            expected.field == position.field
+                && expected.hold == position.hold
+                && expected.current == position.current
+        });
+        if !in_sync {
+            self.start(board);
+        }
+
+        self.send(&FrontendMessage::Suggest);
+        self.suggesting = true;
+        self.asked = Some(Instant::now());
+        None
+    }
+
+    fn failed(&self) -> bool {
+        matches!(self.state, State::Failed(_))
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        match &self.state {
+            State::Failed(reason) => Some(reason),
+            _ => None,
+        }
+    }
+
+    // The bot's move for the active piece once it has one. Some(None) when
+    // the bot has failed or suggested nothing the board allows.
+    pub fn think(&mut self, board: &Board) -> Option<Option<Plan>> {
+        match self.poll(board) {
+            Some(moves) => Some(self.choose(board, &moves)),
+            None => self.failed().then_some(None),
+        }
+    }
+
+    // Every suggestion that fits the board, best first, for a player to pick
+    // from rather than the bot to play. Empty when the bot has failed.
+    pub fn advise(&mut self, board: &Board) -> Option<Vec<Plan>> {
+        match self.poll(board) {
+            Some(moves) => Some(
+                moves
+                    .iter()
+                    .filter_map(|mv| Self::plan_for(board, mv))
+                    .collect(),
+            ),
+            None => self.failed().then(Vec::new),
+        }
+    }
+
+    // Plays the first suggestion that fits and tells the bot about it, along
+    // with any pieces the move brings into the queue
+    fn choose(&mut self, board: &Board, moves: &[TbpMove]) -> Option<Plan> {
+        let Some((mv, plan)) = moves
+            .iter()
+            .find_map(|mv| Self::plan_for(board, mv).map(|plan| (mv, plan)))
+        else {
+            // Start over from whatever the board looks like next time
+            self.expected = None;
+            return None;
+        };
+
+        let mut after = board.clone();
+        crate::bot::play(&mut after, &plan);
+
+        self.send(&FrontendMessage::Play { mv: mv.clone() });
+        let new = dealt(&after) - dealt(board);
+        let upcoming = after.upcoming();
+        for mino in &upcoming[upcoming.len() - new.min(upcoming.len())..] {
+            self.send(&FrontendMessage::NewPiece {
+                piece: piece(*mino),
+            });
+        }
+
+        self.expected = Some(after.position());
+        Some(plan)
+    }
+}
+
+impl Drop for Tbp {
+    fn drop(&mut self) {
+        self.send(&FrontendMessage::Quit);
+        self.stdin = None;
+
+        if let Some(child) = &mut self.child {
+            let deadline = Instant::now() + QUIT_GRACE;
+            while Instant::now() < deadline {
+                if let Ok(Some(_)) = child.try_wait() {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(field: &str, current: TetrominoType) -> Board {
+        let text = format!(
+            "hold -\ncurrent {}\nqueue -\nfield\n{}",
+            current.letter(),
+            field
+        );
+        let mut board = Board::with_seed((10, 20), 0);
+        board.load_position(&Position::from_text(&text).unwrap());
+        board.new_tetromino();
+        board
+    }
+
+    fn location(kind: &str, orientation: &str, x: i32, y: i32) -> Location {
+        Location {
+            kind: kind.to_string(),
+            orientation: orientation.to_string(),
+            x,
+            y,
+        }
+    }
+
+    fn sorted(mut cells: [(i32, i32); 4]) -> [(i32, i32); 4] {
+        cells.sort();
+        cells
+    }
+
+    // The spec marks the centre of each piece: the second cell from the
+    // left for a flat I, the bottom left of an O and the middle of the
+    // three in a row for the rest. Turning clockwise takes the centre along.
+    #[test]
+    fn offsets_match_the_spec_for_every_orientation() {
+        let expected = [
+            (
+                TetrominoType::I,
+                [
+                    [(-1, 0), (0, 0), (1, 0), (2, 0)],
+                    [(0, -2), (0, -1), (0, 0), (0, 1)],
+                    [(-2, 0), (-1, 0), (0, 0), (1, 0)],
+                    [(0, -1), (0, 0), (0, 1), (0, 2)],
+                ],
+            ),
+            (
+                TetrominoType::O,
+                [
+                    [(0, 0), (0, 1), (1, 0), (1, 1)],
+                    [(0, -1), (0, 0), (1, -1), (1, 0)],
+                    [(-1, -1), (-1, 0), (0, -1), (0, 0)],
+                    [(-1, 0), (-1, 1), (0, 0), (0, 1)],
+                ],
+            ),
+            (
+                TetrominoType::T,
+                [
+                    [(-1, 0), (0, 0), (0, 1), (1, 0)],
+                    [(0, -1), (0, 0), (0, 1), (1, 0)],
+                    [(-1, 0), (0, -1), (0, 0), (1, 0)],
+                    [(-1, 0), (0, -1), (0, 0), (0, 1)],
+                ],
+            ),
+            (
+                TetrominoType::L,
+                [
+                    [(-1, 0), (0, 0), (1, 0), (1, 1)],
+                    [(0, -1), (0, 0), (0, 1), (1, -1)],
+                    [(-1, -1), (-1, 0), (0, 0), (1, 0)],
+                    [(-1, 1), (0, -1), (0, 0), (0, 1)],
+                ],
+            ),
+            (
+                TetrominoType::J,
+                [
+                    [(-1, 0), (-1, 1), (0, 0), (1, 0)],
+                    [(0, -1), (0, 0), (0, 1), (1, 1)],
+                    [(-1, 0), (0, 0), (1, -1), (1, 0)],
+                    [(-1, -1), (0, -1), (0, 0), (0, 1)],
+                ],
+            ),
+            (
+                TetrominoType::S,
+                [
+                    [(-1, 0), (0, 0), (0, 1), (1, 1)],
+                    [(0, 0), (0, 1), (1, -1), (1, 0)],
+                    [(-1, -1), (0, -1), (0, 0), (1, 0)],
+                    [(-1, 0), (-1, 1), (0, -1), (0, 0)],
+                ],
+            ),
+            (
+                TetrominoType::Z,
+                [
+                    [(-1, 1), (0, 0), (0, 1), (1, 0)],
+                    [(0, -1), (0, 0), (1, 0), (1, 1)],
+                    [(-1, 0), (0, -1), (0, 0), (1, -1)],
+                    [(-1, -1), (-1, 0), (0, 0), (0, 1)],
+                ],
+            ),
+        ];
+
+        for (mino, orientations) in expected {
+            for (orientation, cells) in orientations.iter().enumerate() {
+                assert_eq!(
+                    sorted(offsets(mino, orientation)),
+                    *cells,
+                    "{:?} {}",
+                    mino,
+                    ORIENTATIONS[orientation]
+                );
+            }
+        }
+    }
+
+    // y counts up from the bottom row, while board rows count down from the
+    // top, so a T resting flat on the floor at x = 4 covers the bottom row
+    // from 3 to 5 and the cell above its centre
+    #[test]
+    fn location_cells_are_board_cells() {
+        let board = board("..........\n", TetrominoType::T);
+        let floor = board.height as i32 - 1;
+
+        let cases = [
+            (
+                location("T", "north", 4, 0),
+                vec![(3, floor), (4, floor - 1), (4, floor), (5, floor)],
+            ),
+            (
+                location("T", "east", 0, 1),
+                vec![(0, floor - 2), (0, floor - 1), (0, floor), (1, floor - 1)],
+            ),
+            (
+                location("T", "south", 4, 1),
+                vec![(3, floor - 1), (4, floor - 1), (4, floor), (5, floor - 1)],
+            ),
+            (
+                location("T", "west", 9, 1),
+                vec![(8, floor - 1), (9, floor - 2), (9, floor - 1), (9, floor)],
+            ),
+            (
+                location("I", "north", 1, 0),
+                vec![(0, floor), (1, floor), (2, floor), (3, floor)],
+            ),
+            (
+                location("I", "east", 0, 2),
+                vec![(0, floor - 3), (0, floor - 2), (0, floor - 1), (0, floor)],
+            ),
+            (
+                location("I", "south", 2, 0),
+                vec![(0, floor), (1, floor), (2, floor), (3, floor)],
+            ),
+            (
+                location("I", "west", 0, 1),
+                vec![(0, floor - 3), (0, floor - 2), (0, floor - 1), (0, floor)],
+            ),
+        ];
+
+        for (location, cells) in cases {
+            assert_eq!(location.cells(&board), Some(cells), "{:?}", location);
+        }
+        assert_eq!(location("T", "up", 4, 0).cells(&board), None);
+        assert_eq!(location("X", "north", 4, 0).cells(&board), None);
+    }
+
+    // The slot under the overhang can only be reached by spinning into it,
+    // so the same cells asked for without a spin are not playable
+    #[test]
+    fn plans_match_the_spin_asked_for() {
+        let board = board("GG........\nG...GGGGGG\nGG.GGGGGGG\n", TetrominoType::T);
+        let tsd = |spin: &str| TbpMove {
+            location: location("T", "south", 2, 1),
+            spin: spin.to_string(),
+        };
+
+        let plan = Tbp::plan_for(&board, &tsd("full")).expect("no plan for the T-spin");
+        assert!(plan.placement.spin);
+        assert!(Tbp::plan_for(&board, &tsd("mini")).is_none());
+        assert!(Tbp::plan_for(&board, &tsd("none")).is_none());
+
+        let flat = TbpMove {
+            location: location("T", "north", 7, 2),
+            spin: "none".to_string(),
+        };
+        assert!(Tbp::plan_for(&board, &flat).is_some());
+    }
+
+    #[test]
+    fn start_sends_the_bag_state() {
+        let start = FrontendMessage::Start {
+            hold: None,
+            queue: vec!["T".to_string()],
+            combo: 0,
+            back_to_back: false,
+            board: Vec::new(),
+            randomizer: Some(Randomizer::SevenBag {
+                bag_state: vec!["I".to_string(), "O".to_string()],
+            }),
+        };
+
+        let json = serde_json::to_value(&start).unwrap();
+        assert_eq!(
+            json["randomizer"],
+            serde_json::json!({ "type": "seven_bag", "bag_state": ["I", "O"] })
+        );
+    }
+}
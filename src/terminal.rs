@@ -0,0 +1,115 @@
+use std::io::{self, stdout};
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crossterm::event::{
+    KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::{cursor, execute, terminal};
+
+// Whether the terminal is set up for the game, so restoring it twice, from
+// the guard and the panic hook say, only undoes the setup once
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+// Owns the terminal for as long as the game runs. Dropping it puts the
+// terminal back the way we found it, whichever way we leave `main`.
+pub struct TerminalGuard;
+
+impl TerminalGuard {
+    pub fn new() -> io::Result<Self> {
+        setup()?;
+        Ok(TerminalGuard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+pub fn setup() -> io::Result<()> {
+    terminal::enable_raw_mode()?;
+    ACTIVE.store(true, Ordering::SeqCst);
+
+    execute!(
+        stdout(),
+        terminal::EnterAlternateScreen,
+        PushKeyboardEnhancementFlags(
+            KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                | KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
+                | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+        ),
+        cursor::Hide,
+        terminal::Clear(terminal::ClearType::All)
+    )
+}
+
+// Also called from the panic hook, so every step is attempted even if an
+// earlier one fails.
+pub fn restore() {
+    if !ACTIVE.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    let mut stdout = stdout();
+
+    let _ = execute!(stdout, PopKeyboardEnhancementFlags);
+    let _ = terminal::disable_raw_mode();
+    let _ = execute!(stdout, cursor::Show, terminal::LeaveAlternateScreen);
+}
+
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        restore();
+        default_hook(info);
+    }));
+}
+
+// Stops the process like the default SIGTSTP handler would, with the terminal
+// handed back to the shell while we are in the background.
+pub fn suspend() -> io::Result<()> {
+    restore();
+
+    #[cfg(unix)]
+    signal_hook::low_level::emulate_default_handler(signal_hook::consts::SIGTSTP)?;
+
+    setup()
+}
+
+pub struct Signals {
+    quit: Arc<AtomicBool>,
+    suspend: Arc<AtomicBool>,
+}
+
+impl Signals {
+    pub fn register() -> io::Result<Self> {
+        let signals = Signals {
+            quit: Arc::new(AtomicBool::new(false)),
+            suspend: Arc::new(AtomicBool::new(false)),
+        };
+
+        #[cfg(unix)]
+        {
+            use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM, SIGTSTP};
+            use signal_hook::flag;
+
+            for signal in [SIGTERM, SIGHUP, SIGINT] {
+                flag::register(signal, Arc::clone(&signals.quit))?;
+            }
+            flag::register(SIGTSTP, Arc::clone(&signals.suspend))?;
+        }
+
+        Ok(signals)
+    }
+
+    pub fn quit_requested(&self) -> bool {
+        self.quit.load(Ordering::Relaxed)
+    }
+
+    pub fn take_suspend(&self) -> bool {
+        self.suspend.swap(false, Ordering::Relaxed)
+    }
+}
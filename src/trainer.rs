@@ -0,0 +1,270 @@
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::board::{Board, Status};
+use crate::mino::TetrominoType;
+use crate::mode::{GameEnd, History, ModeController};
+use crate::pc::{self, Placement, Rules, PC_ROWS};
+use crate::position::Position;
+
+// The first perfect clear uses ten pieces: the first bag and three more
+const OPENER_PIECES: usize = 10;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Opener {
+    // Any ten piece perfect clear from an empty field
+    FirstPc,
+    // Builds with the six other pieces of the first bag and keeps the T for
+    // the clear, like PCO does, but into whatever shape the solver finds
+    // rather than PCO's own. Settings saved before the rename still load.
+    #[serde(alias = "Pco")]
+    SavedT,
+}
+
+impl Opener {
+    pub const ALL: [Opener; 2] = [Opener::FirstPc, Opener::SavedT];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Opener::FirstPc => "First PC",
+            Opener::SavedT => "Saved T",
+        }
+    }
+
+    fn rules(&self) -> Rules {
+        match self {
+            Opener::FirstPc => Rules::default(),
+            Opener::SavedT => Rules {
+                save: Some((TetrominoType::T, 6)),
+                ..Rules::default()
+            },
+        }
+    }
+}
+
+// The solve area bottom row first, each cell holding the piece that filled it
+type Grid = Vec<Vec<Option<TetrominoType>>>;
+
+fn grid_of(position: &Position) -> Grid {
+    let width = position.field[0].len();
+    let mut grid = vec![vec![None; width]; PC_ROWS];
+
+    for (row, cells) in position.field.iter().rev().take(PC_ROWS).enumerate() {
+        for (x, cell) in cells.iter().enumerate() {
+            if let Status::FillType(mino) = cell {
+                grid[row][x] = Some(*mino);
+            }
+        }
+    }
+    grid
+}
+
+// The field after each piece of `plan`, starting with the field before it
+fn expected_grids(start: Grid, plan: &[Placement]) -> Vec<Grid> {
+    let mut grids = vec![start];
+
+    for placement in plan {
+        let mut grid = grids.last().unwrap().clone();
+        for &(x, row) in &placement.cells {
+            grid[row][x] = Some(placement.kind);
+        }
+
+        let width = grid[0].len();
+        grid.retain(|row| row.iter().any(Option::is_none));
+        grid.resize(PC_ROWS, vec![None; width]);
+        grids.push(grid);
+    }
+    grids
+}
+
+fn matches(position: &Position, grid: &Grid) -> bool {
+    position.field.iter().rev().enumerate().all(|(row, cells)| {
+        cells.iter().enumerate().all(|(x, cell)| {
+            let expected = grid.get(row).and_then(|row| row[x]);
+            match cell {
+                Status::FillType(mino) => expected == Some(*mino),
+                Status::Garbage => false,
+                _ => expected.is_none(),
+            }
+        })
+    })
+}
+
+// Drills a perfect clear opener. Each attempt starts from an empty field
+// with fresh bags, the built in solver plans a clear for the queue, and the
+// spot for the next piece is shown on the field. A placement off the plan
+// is flagged straight away and can be undone, or the attempt restarted,
+// automatically if the setting is on.
+pub struct Trainer {
+    opener: Opener,
+    auto_restart: bool,
+    history: History,
+    solving: Option<Receiver<Option<Vec<Placement>>>>,
+    plan: Vec<Placement>,
+    expected: Vec<Grid>,
+    // Pieces placed before the current attempt began
+    start_placed: usize,
+    off_plan: bool,
+    attempts: usize,
+    clears: usize,
+    misses: usize,
+}
+
+impl Trainer {
+    pub fn new(opener: Opener, auto_restart: bool) -> Self {
+        Trainer {
+            opener,
+            auto_restart,
+            history: History::default(),
+            solving: None,
+            plan: Vec::new(),
+            expected: Vec::new(),
+            start_placed: 0,
+            off_plan: false,
+            attempts: 0,
+            clears: 0,
+            misses: 0,
+        }
+    }
+
+    fn step(&self, board: &Board) -> usize {
+        board.pieces_placed - self.start_placed
+    }
+
+    // Clears the field for a new attempt with fresh bags and starts solving
+    // its queue in the background, as a search can take a moment.
+    fn new_attempt(&mut self, board: &mut Board) {
+        board.load_position(&Position::new(board.width));
+        board.new_tetromino();
+
+        self.history = History::default();
+        self.history.record(board);
+        self.start_placed = board.pieces_placed;
+        self.off_plan = false;
+        self.attempts += 1;
+
+        let mut position = board.position();
+        self.plan.clear();
+        self.expected = vec![grid_of(&position)];
+
+        position.queue.truncate(OPENER_PIECES);
+        let rules = self.opener.rules();
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || tx.send(pc::solve(&position, rules)));
+        self.solving = Some(rx);
+    }
+
+    fn receive_plan(&mut self, board: &mut Board) {
+        let Some(solving) = &self.solving else {
+            return;
+        };
+
+        match solving.try_recv() {
+            // Pieces may already be down if the search was slow, so they are
+            // checked against the plan as soon as it arrives
+            Ok(Some(plan)) => {
+                self.expected = expected_grids(self.expected[0].clone(), &plan);
+                self.plan = plan;
+                self.solving = None;
+                self.check(board);
+            }
+            // Some queues have no clear the solver can find, so deal another
+            Ok(None) | Err(TryRecvError::Disconnected) => self.new_attempt(board),
+            Err(TryRecvError::Empty) => (),
+        }
+    }
+
+    // Compares the field with the plan after the pieces placed so far
+    fn check(&mut self, board: &Board) {
+        let on_plan = self
+            .expected
+            .get(self.step(board))
+            .is_some_and(|grid| matches(&board.position(), grid));
+        self.off_plan = !self.plan.is_empty() && !on_plan;
+    }
+
+    fn show_target(&self, board: &mut Board) {
+        match self.plan.get(self.step(board)) {
+            Some(placement) if !self.off_plan => {
+                let cells: Vec<(usize, usize)> = placement
+                    .cells
+                    .iter()
+                    .map(|&(x, row)| (x, board.height - 1 - row))
+                    .collect();
+                board.set_overlay(placement.kind, &cells);
+            }
+            _ => board.clear_overlay(),
+        }
+    }
+}
+
+impl ModeController for Trainer {
+    fn setup(&mut self, board: &mut Board) {
+        board.set_gravity(Duration::MAX);
+        board.auto_lock = false;
+        board.can_top_out = false;
+    }
+
+    fn update(&mut self, board: &mut Board, _elapsed: Duration) -> Option<GameEnd> {
+        if self.attempts == 0 {
+            self.new_attempt(board);
+        }
+        self.receive_plan(board);
+
+        if self.history.record(board) && !self.plan.is_empty() {
+            let was_off_plan = self.off_plan;
+            self.check(board);
+
+            if self.off_plan && !was_off_plan {
+                self.misses += 1;
+                if self.auto_restart {
+                    self.retry(board);
+                }
+            } else if !self.off_plan && self.step(board) == self.plan.len() {
+                self.clears += 1;
+                self.new_attempt(board);
+            }
+        }
+
+        self.show_target(board);
+        None
+    }
+
+    fn hud(&self, board: &Board, _elapsed: Duration) -> Vec<String> {
+        let progress = if self.solving.is_some() {
+            "SOLVING...".to_string()
+        } else if self.off_plan {
+            "OFF PLAN: UNDO/RETRY".to_string()
+        } else {
+            format!("STEP:    {}/{}", self.step(board), self.plan.len())
+        };
+
+        vec![
+            format!("OPENER:  {}", self.opener.name()),
+            format!("PCS:     {}/{}", self.clears, self.attempts),
+            format!("MISSES:  {}", self.misses),
+            progress,
+        ]
+    }
+
+    fn undo(&mut self, board: &mut Board) {
+        if self.step(board) > 0 && self.history.undo(board) {
+            self.check(board);
+        }
+    }
+
+    fn redo(&mut self, board: &mut Board) {
+        if self.history.redo(board) {
+            self.check(board);
+        }
+    }
+
+    fn retry(&mut self, board: &mut Board) {
+        self.history.rewind(board);
+        self.off_plan = false;
+    }
+}
@@ -0,0 +1,182 @@
+use std::io::{stdout, Stdout, Write};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{Clear, ClearType};
+use crossterm::{cursor, execute, queue};
+
+use crate::terminal::{self, Signals};
+use crate::util::base64;
+
+// Returned up the call stack when the whole program should exit.
+#[derive(Debug)]
+pub struct Quit;
+
+fn input_thread(tx: Sender<Event>) {
+    loop {
+        if event::poll(Duration::from_millis(10)).unwrap() {
+            if let Ok(key_event) = event::read() {
+                if tx.send(key_event).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+pub struct Ui {
+    pub stdout: Stdout,
+    events: Receiver<Event>,
+    signals: Signals,
+    suspended: Duration,
+}
+
+impl Ui {
+    pub fn new(signals: Signals) -> Self {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || input_thread(tx));
+
+        Ui {
+            stdout: stdout(),
+            events: rx,
+            signals,
+            suspended: Duration::ZERO,
+        }
+    }
+
+    fn suspend(&mut self) {
+        let suspended_at = Instant::now();
+        terminal::suspend().unwrap();
+        self.suspended += suspended_at.elapsed();
+    }
+
+    // Time spent stopped in the background since the last call, so game
+    // clocks can skip over it.
+    pub fn take_suspended(&mut self) -> Duration {
+        std::mem::take(&mut self.suspended)
+    }
+
+    fn check_signals(&mut self) -> Result<(), Quit> {
+        if self.signals.quit_requested() {
+            return Err(Quit);
+        }
+
+        if self.signals.take_suspend() {
+            self.suspend();
+        }
+
+        Ok(())
+    }
+
+    // Ctrl-C and Ctrl-Z are handled here for every screen; raw mode swallows
+    // ISIG, so they arrive as keys instead of SIGINT and SIGTSTP.
+    fn filter(&mut self, event: Event) -> Result<Option<KeyEvent>, Quit> {
+        if let Event::Resize(_, _) = event {
+            self.clear();
+        }
+
+        if let Event::Key(key) = event {
+            if key.modifiers.contains(KeyModifiers::CONTROL) {
+                match key.code {
+                    KeyCode::Char('c') => return Err(Quit),
+                    KeyCode::Char('z') => {
+                        self.suspend();
+                        return Ok(None);
+                    }
+                    _ => (),
+                }
+            }
+            return Ok(Some(key));
+        }
+        Ok(None)
+    }
+
+    pub fn try_key(&mut self) -> Result<Option<KeyEvent>, Quit> {
+        self.check_signals()?;
+
+        while let Ok(event) = self.events.try_recv() {
+            if let Some(key) = self.filter(event)? {
+                return Ok(Some(key));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Blocks until a key is pressed, ignoring releases and repeats.
+    pub fn wait_key(&mut self) -> Result<KeyEvent, Quit> {
+        loop {
+            self.check_signals()?;
+
+            if let Ok(event) = self.events.recv_timeout(Duration::from_millis(50)) {
+                if let Some(key) = self.filter(event)? {
+                    if key.kind == KeyEventKind::Press {
+                        return Ok(key);
+                    }
+                }
+            }
+        }
+    }
+
+    // Waits for a key, redrawing every `interval` so screens can follow
+    // terminal resizes and come back after a suspend.
+    pub fn wait_key_redraw(
+        &mut self,
+        interval: Duration,
+        mut draw: impl FnMut(&mut Self),
+    ) -> Result<KeyEvent, Quit> {
+        loop {
+            draw(self);
+            let deadline = Instant::now() + interval;
+
+            while Instant::now() < deadline {
+                self.check_signals()?;
+
+                if let Ok(event) = self.events.recv_timeout(Duration::from_millis(20)) {
+                    if let Some(key) = self.filter(event)? {
+                        if key.kind == KeyEventKind::Press {
+                            return Ok(key);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        execute!(self.stdout, Clear(ClearType::All)).unwrap();
+    }
+
+    pub fn print_at(&mut self, x: u16, y: u16, text: &str) {
+        queue!(self.stdout, cursor::MoveTo(x, y), Print(text)).unwrap();
+    }
+
+    pub fn print_centered(&mut self, y: u16, text: &str) {
+        let (cols, _) = crossterm::terminal::size().unwrap();
+        let width = text.chars().count() as u16;
+        self.print_at(cols.saturating_sub(width) / 2, y, text);
+    }
+
+    pub fn print_centered_styled(&mut self, y: u16, text: &str, attribute: Attribute) {
+        queue!(self.stdout, SetAttribute(attribute)).unwrap();
+        self.print_centered(y, text);
+        queue!(self.stdout, SetAttribute(Attribute::Reset)).unwrap();
+    }
+
+    // Puts text on the system clipboard through the OSC 52 escape. Terminals
+    // that don't support it ignore the sequence.
+    pub fn copy_to_clipboard(&mut self, text: &str) {
+        queue!(
+            self.stdout,
+            Print(format!("\x1b]52;c;{}\x07", base64(text.as_bytes())))
+        )
+        .unwrap();
+    }
+
+    pub fn flush(&mut self) {
+        self.stdout.flush().unwrap();
+    }
+}
@@ -1,12 +1,16 @@
 use crate::constants::{
-    BLUE_GHOST, BLUE_TILE, CYAN_GHOST, CYAN_TILE, GREEN_GHOST, GREEN_TILE, MAGENTA_GHOST,
-    MAGENTA_TILE, ORANGE_GHOST, ORANGE_TILE, RED_GHOST, RED_TILE, YELLOW_GHOST, YELLOW_TILE,
+    BLUE_GHOST, BLUE_HINT, BLUE_OVERLAY, BLUE_TILE, CYAN_GHOST, CYAN_HINT, CYAN_OVERLAY, CYAN_TILE,
+    GREEN_GHOST, GREEN_HINT, GREEN_OVERLAY, GREEN_TILE, MAGENTA_GHOST, MAGENTA_HINT,
+    MAGENTA_OVERLAY, MAGENTA_TILE, ORANGE_GHOST, ORANGE_HINT, ORANGE_OVERLAY, ORANGE_TILE,
+    RED_GHOST, RED_HINT, RED_OVERLAY, RED_TILE, YELLOW_GHOST, YELLOW_HINT, YELLOW_OVERLAY,
+    YELLOW_TILE,
 };
 use crate::mino::{GhostType, TetrominoType};
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::Rng;
+use std::time::Duration;
 
-pub fn gen_bag() -> Vec<TetrominoType> {
+pub fn gen_bag(rng: &mut impl Rng) -> Vec<TetrominoType> {
     let mut bag = vec![
         TetrominoType::I,
         TetrominoType::O,
@@ -19,9 +23,7 @@ pub fn gen_bag() -> Vec<TetrominoType> {
 
     // let mut bag = vec![TetrominoType::I; 7];
 
-    let mut rng = thread_rng();
-
-    bag.shuffle(&mut rng);
+    bag.shuffle(rng);
     bag
 }
 
@@ -49,6 +51,30 @@ pub fn get_ghost_color(mino: GhostType) -> &'static str {
     }
 }
 
+pub fn get_overlay_color(mino: TetrominoType) -> &'static str {
+    match mino {
+        TetrominoType::I => CYAN_OVERLAY,
+        TetrominoType::O => YELLOW_OVERLAY,
+        TetrominoType::T => MAGENTA_OVERLAY,
+        TetrominoType::S => GREEN_OVERLAY,
+        TetrominoType::Z => RED_OVERLAY,
+        TetrominoType::J => BLUE_OVERLAY,
+        TetrominoType::L => ORANGE_OVERLAY,
+    }
+}
+
+pub fn get_hint_color(mino: TetrominoType) -> &'static str {
+    match mino {
+        TetrominoType::I => CYAN_HINT,
+        TetrominoType::O => YELLOW_HINT,
+        TetrominoType::T => MAGENTA_HINT,
+        TetrominoType::S => GREEN_HINT,
+        TetrominoType::Z => RED_HINT,
+        TetrominoType::J => BLUE_HINT,
+        TetrominoType::L => ORANGE_HINT,
+    }
+}
+
 pub fn mino_to_ghost(mino: TetrominoType) -> GhostType {
     match mino {
         TetrominoType::I => GhostType::I,
@@ -60,3 +86,66 @@ pub fn mino_to_ghost(mino: TetrominoType) -> GhostType {
         TetrominoType::L => GhostType::L,
     }
 }
+
+pub fn format_time(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    format!(
+        "{}:{:02}.{:03}",
+        millis / 60_000,
+        (millis / 1000) % 60,
+        millis % 1000
+    )
+}
+
+// Longer spans like total time played, as h:mm:ss
+pub fn format_hours(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60)
+}
+
+// Stable across builds and platforms, unlike std's DefaultHasher, so it can
+// be written to disk.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// Standard padded base64, for the terminal clipboard escape
+pub fn base64(bytes: &[u8]) -> String {
+    const TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| {
+            bits | (*byte as u32) << (16 - 8 * i)
+        });
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(TABLE[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+// Unix seconds as a UTC "YYYY-MM-DD" date.
+pub fn format_date(unix_secs: u64) -> String {
+    let days = (unix_secs / 86_400) as i64;
+
+    // Civil from days, after Howard Hinnant's date algorithms
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
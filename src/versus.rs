@@ -0,0 +1,127 @@
+use std::time::Duration;
+
+use crate::board::Board;
+use crate::bot::Bot;
+use crate::constants::{HEIGHT, WIDTH};
+use crate::garbage::{GarbageQueue, HoleGenerator};
+use crate::mode::{GameEnd, ModeController};
+
+// Versus garbage mostly lines up, with the odd switch to dig through
+const MESSINESS: u8 = 30;
+
+// One player's half of the garbage exchange
+pub struct Side {
+    pub garbage: GarbageQueue,
+    holes: HoleGenerator,
+    placed: usize,
+    attack: usize,
+    lines: usize,
+    pub sent: usize,
+    pub received: usize,
+}
+
+impl Side {
+    pub fn new(seed: u64) -> Self {
+        Side {
+            garbage: GarbageQueue::default(),
+            holes: HoleGenerator::new(seed, true, MESSINESS),
+            placed: 0,
+            attack: 0,
+            lines: 0,
+            sent: 0,
+            received: 0,
+        }
+    }
+
+    // Settles the garbage for a new placement on `board`, returning the lines
+    // it sends to the other side
+    pub fn exchange(&mut self, board: &mut Board) -> usize {
+        if board.pieces_placed == self.placed {
+            return 0;
+        }
+        self.placed = board.pieces_placed;
+
+        let attack = board.stats.attack - self.attack;
+        let cleared = board.lines_cleared != self.lines;
+        self.attack = board.stats.attack;
+        self.lines = board.lines_cleared;
+
+        let sent = self.garbage.counter(attack);
+        self.sent += sent;
+
+        if !cleared {
+            let rows = self.garbage.take();
+            self.received += rows;
+            board.add_garbage(&self.holes.holes(board.width, rows));
+        }
+        sent
+    }
+}
+
+// A race against the bot on a board of its own, dealt the same pieces.
+// Attack sends garbage across, and the first to top out loses.
+pub struct Versus {
+    bot: Bot,
+    opponent: Board,
+    player: Side,
+    rival: Side,
+}
+
+impl Versus {
+    pub fn new(bot: Bot) -> Self {
+        Versus {
+            bot,
+            opponent: Board::new((WIDTH, HEIGHT)),
+            player: Side::new(0),
+            rival: Side::new(0),
+        }
+    }
+}
+
+impl ModeController for Versus {
+    fn setup(&mut self, board: &mut Board) {
+        self.opponent = Board::with_seed((board.width, board.height - 10), board.seed());
+        self.opponent.new_tetromino();
+        self.player = Side::new(board.seed());
+        self.rival = Side::new(board.seed());
+    }
+
+    fn update(&mut self, board: &mut Board, elapsed: Duration) -> Option<GameEnd> {
+        self.opponent.update(elapsed);
+        self.bot.update(&mut self.opponent, elapsed);
+
+        let sent = self.player.exchange(board);
+        self.rival.garbage.receive(sent);
+        let sent = self.rival.exchange(&mut self.opponent);
+        self.player.garbage.receive(sent);
+
+        if board.topped_out {
+            Some(GameEnd::ToppedOut)
+        } else if self.opponent.topped_out {
+            Some(GameEnd::Won)
+        } else {
+            None
+        }
+    }
+
+    fn hud(&self, _board: &Board, _elapsed: Duration) -> Vec<String> {
+        vec![
+            format!("BOT:     {:.13}", self.bot.name()),
+            format!("BOT PPS: {}", self.bot.pps()),
+            format!("SENT:    {}", self.player.sent),
+            format!("GARBAGE: {}", self.player.garbage.pending()),
+        ]
+    }
+
+    fn results(&self, _board: &Board, _elapsed: Duration) -> Vec<String> {
+        vec![
+            format!("Sent     {:>10}", self.player.sent),
+            format!("Received {:>10}", self.player.received),
+            format!("Bot sent {:>10}", self.rival.sent),
+        ]
+    }
+
+    fn opponent(&self) -> Option<&Board> {
+        Some(&self.opponent)
+    }
+}